@@ -1,9 +1,18 @@
-use super::models::{AwsCliOutput, MfaDevice, SessionCredentials, VirtualMfaDevice};
+use super::aws_error::with_retry;
+use super::backend::AwsBackend;
+use super::cancellation;
+use super::settings::AwsSettings;
+use super::tooling::Tooling;
+use super::models::{
+    AssumeRoleRequest, AwsCliOutput, CallerIdentity, MfaDevice, SessionCredentials,
+    SsoRoleCredentialsOutput, VirtualMfaDevice,
+};
 use crate::error::AppError;
+use async_trait::async_trait;
 use std::collections::HashMap;
 use std::process::Stdio;
-use tokio::process::Command;
 
+/// Backend shelling out to the `aws` CLI.
 pub struct AwsCli;
 
 impl AwsCli {
@@ -11,11 +20,27 @@ impl AwsCli {
         args: Vec<&str>,
         env: Option<HashMap<String, String>>,
     ) -> crate::error::Result<String> {
-        let mut command = Command::new("aws");
+        let settings = AwsSettings::load();
+        let operation = args.iter().take(2).copied().collect::<Vec<_>>().join(" ");
+
+        with_retry(&operation, || {
+            Self::run_once(&args, env.as_ref(), &settings, &operation)
+        })
+        .await
+    }
+
+    async fn run_once(
+        args: &[&str],
+        env: Option<&HashMap<String, String>>,
+        settings: &AwsSettings,
+        operation: &str,
+    ) -> crate::error::Result<String> {
+        let mut command = Tooling::aws_command(settings);
         command
             .args(args)
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
 
         if let Some(env_vars) = env {
             for (key, value) in env_vars {
@@ -23,7 +48,16 @@ impl AwsCli {
             }
         }
 
-        let output = command.spawn()?.wait_with_output().await?;
+        let child = command.spawn().map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::Custom("AWS CLI not found".to_string()),
+            _ => e.into(),
+        })?;
+        let output = cancellation::guard(
+            operation,
+            settings.command_timeout(operation),
+            async { Ok(child.wait_with_output().await?) },
+        )
+        .await?;
 
         if output.status.success() {
             let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
@@ -34,7 +68,25 @@ impl AwsCli {
         }
     }
 
-    pub async fn create_virtual_mfa_device(
+    fn parse_credentials(output: &str) -> crate::error::Result<SessionCredentials> {
+        let parsed: AwsCliOutput = serde_json::from_str(output)?;
+        let creds = parsed
+            .credentials
+            .ok_or_else(|| AppError::AwsCli("No credentials in response".to_string()))?;
+
+        Ok(SessionCredentials {
+            access_key_id: creds.access_key_id,
+            secret_access_key: creds.secret_access_key,
+            session_token: creds.session_token,
+            expiration: creds.expiration,
+        })
+    }
+}
+
+#[async_trait]
+impl AwsBackend for AwsCli {
+    async fn create_virtual_mfa_device(
+        &self,
         username: &str,
         outfile: &str,
         profile: Option<&str>,
@@ -60,7 +112,8 @@ impl AwsCli {
             .ok_or_else(|| AppError::AwsCli("No virtual MFA device in response".to_string()))
     }
 
-    pub async fn enable_mfa_device(
+    async fn enable_mfa_device(
+        &self,
         username: &str,
         serial_number: &str,
         code1: &str,
@@ -87,7 +140,8 @@ impl AwsCli {
         Ok(())
     }
 
-    pub async fn get_session_token(
+    async fn get_session_token(
+        &self,
         serial_number: &str,
         token_code: &str,
         profile: Option<&str>,
@@ -105,20 +159,68 @@ impl AwsCli {
         }
 
         let output = Self::run_command(args, None).await?;
-        let parsed: AwsCliOutput = serde_json::from_str(&output)?;
-        let creds = parsed
-            .credentials
-            .ok_or_else(|| AppError::AwsCli("No credentials in response".to_string()))?;
+        Self::parse_credentials(&output)
+    }
 
-        Ok(SessionCredentials {
-            access_key_id: creds.access_key_id,
-            secret_access_key: creds.secret_access_key,
-            session_token: creds.session_token,
-            expiration: creds.expiration,
-        })
+    async fn assume_role(
+        &self,
+        request: &AssumeRoleRequest,
+        profile: Option<&str>,
+    ) -> crate::error::Result<SessionCredentials> {
+        let duration = request.duration_seconds.map(|d| d.to_string());
+
+        let mut args = vec![
+            "sts",
+            "assume-role",
+            "--role-arn",
+            &request.role_arn,
+            "--role-session-name",
+            &request.role_session_name,
+        ];
+        if let (Some(serial), Some(code)) = (&request.serial_number, &request.token_code) {
+            args.extend_from_slice(&["--serial-number", serial, "--token-code", code]);
+        }
+        if let Some(duration) = &duration {
+            args.extend_from_slice(&["--duration-seconds", duration]);
+        }
+        if let Some(external_id) = &request.external_id {
+            args.extend_from_slice(&["--external-id", external_id]);
+        }
+        if let Some(p) = profile {
+            args.extend_from_slice(&["--profile", p]);
+        }
+
+        let output = Self::run_command(args, None).await?;
+        Self::parse_credentials(&output)
+    }
+
+    async fn get_role_credentials(
+        &self,
+        account_id: &str,
+        role_name: &str,
+        access_token: &str,
+        region: &str,
+    ) -> crate::error::Result<SessionCredentials> {
+        let args = vec![
+            "sso",
+            "get-role-credentials",
+            "--account-id",
+            account_id,
+            "--role-name",
+            role_name,
+            "--access-token",
+            access_token,
+            "--region",
+            region,
+        ];
+
+        let output = Self::run_command(args, None).await?;
+        let parsed: SsoRoleCredentialsOutput = serde_json::from_str(&output)?;
+        Ok(parsed.role_credentials.into_session_credentials())
     }
 
-    pub async fn list_mfa_devices(
+    async fn list_mfa_devices(
+        &self,
         username: &str,
         profile: Option<&str>,
     ) -> crate::error::Result<Vec<MfaDevice>> {
@@ -132,9 +234,10 @@ impl AwsCli {
         Ok(parsed.mfa_devices.unwrap_or_default())
     }
 
-    pub async fn get_caller_identity(
+    async fn get_caller_identity(
+        &self,
         profile: Option<&str>,
-    ) -> crate::error::Result<serde_json::Value> {
+    ) -> crate::error::Result<CallerIdentity> {
         let mut args = vec!["sts", "get-caller-identity"];
         if let Some(p) = profile {
             args.extend_from_slice(&["--profile", p]);
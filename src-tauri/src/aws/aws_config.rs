@@ -1,20 +1,94 @@
-use super::aws_cli::AwsCli;
+use super::backend::AwsBackend;
 use super::utils::{get_aws_config_path, get_aws_credentials_path};
 use crate::error::{AppError, Result};
-use ini::Ini;
+use ini::{Ini, Properties};
+use serde::Serialize;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
+/// A profile of the shared config, with the settings akaw and the AWS CLI act on.
+#[derive(Debug, Clone, Serialize)]
 pub struct AwsProfile {
     pub name: String,
     pub region: Option<String>,
     pub output: Option<String>,
+    pub role_arn: Option<String>,
+    pub source_profile: Option<String>,
+    pub mfa_serial: Option<String>,
+    pub duration_seconds: Option<u32>,
+    pub role_session_name: Option<String>,
+    pub external_id: Option<String>,
+    pub sso_session: Option<String>,
+    pub sso_start_url: Option<String>,
+    pub sso_region: Option<String>,
+    pub sso_account_id: Option<String>,
+    pub sso_role_name: Option<String>,
+    pub sso_registration_scopes: Option<String>,
+    pub credential_process: Option<String>,
+    pub endpoint_url: Option<String>,
+    pub ca_bundle: Option<String>,
+    /// Name of the `[services]` section with per-service endpoint overrides.
+    pub services: Option<String>,
 }
 
+impl AwsProfile {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            region: None,
+            output: None,
+            role_arn: None,
+            source_profile: None,
+            mfa_serial: None,
+            duration_seconds: None,
+            role_session_name: None,
+            external_id: None,
+            sso_session: None,
+            sso_start_url: None,
+            sso_region: None,
+            sso_account_id: None,
+            sso_role_name: None,
+            sso_registration_scopes: None,
+            credential_process: None,
+            endpoint_url: None,
+            ca_bundle: None,
+            services: None,
+        }
+    }
+
+    /// Role profiles get their credentials from `sts assume-role` through a source profile.
+    pub fn is_role(&self) -> bool {
+        self.role_arn.is_some()
+    }
+
+    /// SSO profiles get role credentials from IAM Identity Center and need no TOTP secret.
+    pub fn is_sso(&self) -> bool {
+        self.sso_account_id.is_some() && self.sso_role_name.is_some()
+    }
+}
+
+/// An `[sso-session]` section, shared by the SSO profiles signing in through it.
+#[derive(Debug, Clone, Serialize)]
+pub struct SsoSession {
+    pub name: String,
+    pub sso_start_url: Option<String>,
+    pub sso_region: Option<String>,
+    pub sso_registration_scopes: Option<String>,
+}
+
+/// A `[services]` section: endpoint settings per service, keyed by the service identifier.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServicesSection {
+    pub name: String,
+    pub services: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+const SSO_SESSION_PREFIX: &str = "sso-session ";
+const SERVICES_PREFIX: &str = "services ";
+
 pub struct AwsConfig;
 
 impl AwsConfig {
-    pub fn list_profiles() -> Result<Vec<AwsProfile>> {
+    fn load_config() -> Result<Ini> {
         let config_path = get_aws_config_path()?;
 
         if !config_path.exists() {
@@ -23,13 +97,23 @@ impl AwsConfig {
             ));
         }
 
-        let conf = Ini::load_from_file(&config_path)
-            .map_err(|e| AppError::Custom(format!("Failed to read config: {}", e)))?;
+        Ini::load_from_file(&config_path)
+            .map_err(|e| AppError::Custom(format!("Failed to read config: {}", e)))
+    }
+
+    pub fn list_profiles() -> Result<Vec<AwsProfile>> {
+        let conf = Self::load_config()?;
 
         let mut profiles = Vec::new();
 
         for (section, properties) in conf.iter() {
             if let Some(section_name) = section {
+                if section_name.starts_with(SSO_SESSION_PREFIX)
+                    || section_name.starts_with(SERVICES_PREFIX)
+                {
+                    continue;
+                }
+
                 let profile_name = if section_name == "default" {
                     "default".to_string()
                 } else {
@@ -39,10 +123,42 @@ impl AwsConfig {
                         .to_string()
                 };
 
+                let get = |key: &str| properties.get(key).map(|s| s.to_string());
+
+                // Start URL and region of an `sso_session` live in its own section
+                let sso_session = get("sso_session");
+                let session_section = sso_session
+                    .as_ref()
+                    .and_then(|name| conf.section(Some(format!("{}{}", SSO_SESSION_PREFIX, name))));
+                let sso_get = |key: &str| {
+                    session_section
+                        .and_then(|section| section.get(key))
+                        .or_else(|| properties.get(key))
+                        .map(|s| s.to_string())
+                };
+
                 profiles.push(AwsProfile {
-                    name: profile_name,
-                    region: properties.get("region").map(|s| s.to_string()),
-                    output: properties.get("output").map(|s| s.to_string()),
+                    region: get("region"),
+                    output: get("output"),
+                    role_arn: get("role_arn"),
+                    source_profile: get("source_profile"),
+                    mfa_serial: get("mfa_serial"),
+                    duration_seconds: properties
+                        .get("duration_seconds")
+                        .and_then(|s| s.trim().parse().ok()),
+                    role_session_name: get("role_session_name"),
+                    external_id: get("external_id"),
+                    sso_start_url: sso_get("sso_start_url"),
+                    sso_region: sso_get("sso_region"),
+                    sso_session,
+                    sso_account_id: get("sso_account_id"),
+                    sso_role_name: get("sso_role_name"),
+                    sso_registration_scopes: sso_get("sso_registration_scopes"),
+                    credential_process: get("credential_process"),
+                    endpoint_url: get("endpoint_url"),
+                    ca_bundle: get("ca_bundle"),
+                    services: get("services"),
+                    ..AwsProfile::new(profile_name)
                 });
             }
         }
@@ -53,11 +169,7 @@ impl AwsConfig {
                     for (section, _) in creds.iter() {
                         if let Some(section_name) = section {
                             if !profiles.iter().any(|p| p.name == section_name) {
-                                profiles.push(AwsProfile {
-                                    name: section_name.to_string(),
-                                    region: None,
-                                    output: None,
-                                });
+                                profiles.push(AwsProfile::new(section_name.to_string()));
                             }
                         }
                     }
@@ -83,23 +195,83 @@ impl AwsConfig {
             .ok_or_else(|| AppError::ProfileNotFound(name.to_string()))
     }
 
+    /// The `[sso-session]` section of that name.
+    pub fn sso_session(name: &str) -> Result<Option<SsoSession>> {
+        let conf = Self::load_config()?;
+        let Some(section) = conf.section(Some(format!("{}{}", SSO_SESSION_PREFIX, name))) else {
+            return Ok(None);
+        };
+
+        let get = |key: &str| section.get(key).map(|s| s.to_string());
+        Ok(Some(SsoSession {
+            name: name.to_string(),
+            sso_start_url: get("sso_start_url"),
+            sso_region: get("sso_region"),
+            sso_registration_scopes: get("sso_registration_scopes"),
+        }))
+    }
+
+    /// The `[services]` section of that name.
+    pub fn services(name: &str) -> Result<Option<ServicesSection>> {
+        let conf = Self::load_config()?;
+
+        Ok(conf
+            .section(Some(format!("{}{}", SERVICES_PREFIX, name)))
+            .map(|section| ServicesSection {
+                name: name.to_string(),
+                services: Self::parse_services(section),
+            }))
+    }
+
+    /// Services are nested: `s3 =` with an empty value opens a service and the indented
+    /// lines below it hold its settings. The parser flattens them, in file order.
+    fn parse_services(section: &Properties) -> BTreeMap<String, BTreeMap<String, String>> {
+        let mut services: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        let mut current = None;
+
+        for (key, value) in section.iter() {
+            if value.trim().is_empty() {
+                current = Some(key.to_string());
+                services.entry(key.to_string()).or_default();
+            } else if let Some(service) = &current {
+                services
+                    .entry(service.clone())
+                    .or_default()
+                    .insert(key.to_string(), value.trim().to_string());
+            }
+        }
+
+        services
+    }
+
+    /// The profile holding the access keys and the MFA device: the source profile for roles,
+    /// the profile itself otherwise.
+    pub fn mfa_profile(name: &str) -> Result<String> {
+        let profile = Self::get_profile(name)?;
+
+        if !profile.is_role() {
+            return Ok(profile.name);
+        }
+
+        profile.source_profile.ok_or_else(|| {
+            AppError::Custom(format!(
+                "Role profile '{}' has no source_profile. Only source profiles are supported",
+                name
+            ))
+        })
+    }
+
     pub fn profile_exists(name: &str) -> bool {
         Self::get_profile(name).is_ok()
     }
 
-    pub async fn get_username(profile: &str) -> Result<String> {
-        let identity = AwsCli::get_caller_identity(Some(profile)).await?;
+    pub async fn get_username(backend: &dyn AwsBackend, profile: &str) -> Result<String> {
+        let identity = backend.get_caller_identity(Some(profile)).await?;
 
-        let arn = identity["Arn"]
-            .as_str()
-            .ok_or_else(|| AppError::Custom("No Arn in identity response".to_string()))?;
-
-        let username = arn
-            .split('/')
-            .last()
-            .ok_or_else(|| AppError::Custom("Cannot extract username from ARN".to_string()))?
-            .to_string();
+        if identity.arn.is_empty() {
+            return Err(AppError::Custom("No Arn in identity response".to_string()));
+        }
 
-        Ok(username)
+        Ok(identity.username())
     }
 }
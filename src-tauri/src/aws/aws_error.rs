@@ -0,0 +1,147 @@
+use super::cancellation;
+use crate::error::{AppError, Result};
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// Operations that create or change something and fail when repeated, e.g. with
+/// `EntityAlreadyExists`. A request that failed on the way back may already have been applied,
+/// so they are never retried.
+const NON_IDEMPOTENT_OPERATIONS: [&str; 2] =
+    ["iam create-virtual-mfa-device", "iam enable-mfa-device"];
+
+/// What went wrong in an AWS call, derived from the error code in the CLI or API output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AwsErrorKind {
+    ExpiredToken,
+    AccessDenied,
+    InvalidClientTokenId,
+    Throttling,
+    ServiceUnavailable,
+    /// The one-time code was rejected, usually because it was already used in this window.
+    InvalidMfaCode,
+    Network,
+    Other(String),
+}
+
+impl AwsErrorKind {
+    /// Errors worth retrying with the same request.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            AwsErrorKind::Throttling | AwsErrorKind::ServiceUnavailable | AwsErrorKind::Network
+        )
+    }
+
+    /// Classify `An error occurred (Code) when calling the X operation: message` output,
+    /// as printed by the CLI and produced by the native backend.
+    pub fn parse(text: &str) -> Option<Self> {
+        let lower = text.to_lowercase();
+
+        if lower.contains("one time pass code") || lower.contains("invalidauthenticationcode") {
+            return Some(AwsErrorKind::InvalidMfaCode);
+        }
+
+        if let Some(code) = error_code(text) {
+            return Some(Self::from_code(code));
+        }
+
+        const NETWORK_MARKERS: [&str; 5] = [
+            "could not connect to the endpoint url",
+            "connect timeout on endpoint url",
+            "read timeout on endpoint url",
+            "connection was closed before we received a valid response",
+            "ssl validation failed",
+        ];
+
+        NETWORK_MARKERS
+            .iter()
+            .any(|marker| lower.contains(marker))
+            .then_some(AwsErrorKind::Network)
+    }
+
+    fn from_code(code: &str) -> Self {
+        match code {
+            "ExpiredToken" | "ExpiredTokenException" => AwsErrorKind::ExpiredToken,
+            "AccessDenied" | "AccessDeniedException" => AwsErrorKind::AccessDenied,
+            "InvalidClientTokenId" | "UnrecognizedClientException" => {
+                AwsErrorKind::InvalidClientTokenId
+            }
+            "Throttling"
+            | "ThrottlingException"
+            | "RequestLimitExceeded"
+            | "TooManyRequestsException"
+            | "RequestThrottled"
+            | "SlowDown" => AwsErrorKind::Throttling,
+            "ServiceUnavailable" | "InternalFailure" | "InternalError" => {
+                AwsErrorKind::ServiceUnavailable
+            }
+            "InvalidAuthenticationCode" => AwsErrorKind::InvalidMfaCode,
+            other => AwsErrorKind::Other(other.to_string()),
+        }
+    }
+}
+
+impl AppError {
+    /// The AWS error kind behind this error, when it came from an AWS call.
+    pub fn aws_kind(&self) -> Option<AwsErrorKind> {
+        match self {
+            AppError::AwsCli(text) | AppError::Aws(text) => AwsErrorKind::parse(text),
+            AppError::Http(_) => Some(AwsErrorKind::Network),
+            _ => None,
+        }
+    }
+}
+
+/// Run an AWS call, retrying throttling and network failures with jittered exponential backoff.
+/// Operations that are not idempotent run once.
+pub async fn with_retry<T, F, Fut>(operation: &str, mut call: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let max_attempts = if NON_IDEMPOTENT_OPERATIONS.contains(&operation) {
+        1
+    } else {
+        MAX_ATTEMPTS
+    };
+    let mut attempt = 1;
+
+    loop {
+        match call().await {
+            Err(e) if attempt < max_attempts && e.aws_kind().is_some_and(|k| k.is_transient()) => {
+                let delay = backoff(attempt);
+                log::warn!(
+                    "{} failed (attempt {}), retrying in {}ms: {}",
+                    operation,
+                    attempt,
+                    delay.as_millis(),
+                    e
+                );
+                cancellation::sleep(delay).await?;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// "Full jitter": a random delay up to the exponential cap for this attempt.
+fn backoff(attempt: u32) -> Duration {
+    let cap = (BASE_DELAY * 2u32.pow(attempt - 1)).min(MAX_DELAY);
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_millis(random % (cap.as_millis() as u64 + 1))
+}
+
+fn error_code(text: &str) -> Option<&str> {
+    let start = text.find("An error occurred (")? + "An error occurred (".len();
+    let end = text[start..].find(')')? + start;
+    Some(&text[start..end])
+}
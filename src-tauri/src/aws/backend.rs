@@ -0,0 +1,77 @@
+use super::aws_cli::AwsCli;
+use super::models::{
+    AssumeRoleRequest, CallerIdentity, MfaDevice, SessionCredentials, VirtualMfaDevice,
+};
+use super::native_client::NativeBackend;
+use super::settings::{AwsSettings, BackendKind};
+use crate::error::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// The AWS operations akaw depends on. Implemented by the CLI wrapper, the native HTTP
+/// client and by the in-memory fake used to exercise flows offline.
+#[async_trait]
+pub trait AwsBackend: Send + Sync {
+    async fn create_virtual_mfa_device(
+        &self,
+        username: &str,
+        outfile: &str,
+        profile: Option<&str>,
+    ) -> Result<VirtualMfaDevice>;
+
+    async fn enable_mfa_device(
+        &self,
+        username: &str,
+        serial_number: &str,
+        code1: &str,
+        code2: &str,
+        profile: Option<&str>,
+    ) -> Result<()>;
+
+    async fn get_session_token(
+        &self,
+        serial_number: &str,
+        token_code: &str,
+        profile: Option<&str>,
+    ) -> Result<SessionCredentials>;
+
+    async fn assume_role(
+        &self,
+        request: &AssumeRoleRequest,
+        profile: Option<&str>,
+    ) -> Result<SessionCredentials>;
+
+    /// Exchange an IAM Identity Center access token for role credentials.
+    async fn get_role_credentials(
+        &self,
+        account_id: &str,
+        role_name: &str,
+        access_token: &str,
+        region: &str,
+    ) -> Result<SessionCredentials>;
+
+    async fn list_mfa_devices(
+        &self,
+        username: &str,
+        profile: Option<&str>,
+    ) -> Result<Vec<MfaDevice>>;
+
+    async fn get_caller_identity(&self, profile: Option<&str>) -> Result<CallerIdentity>;
+}
+
+/// Backend shared through Tauri state.
+pub type SharedBackend = Arc<dyn AwsBackend>;
+
+/// Build the backend selected in the settings.
+pub fn create_backend() -> SharedBackend {
+    let settings = AwsSettings::load();
+
+    match settings.backend {
+        BackendKind::Cli => Arc::new(AwsCli),
+        BackendKind::Native => Arc::new(NativeBackend::new(
+            &settings.sts_endpoint,
+            &settings.iam_endpoint,
+            settings.sso_portal_endpoint.as_deref(),
+        )),
+    }
+}
@@ -0,0 +1,120 @@
+use crate::error::{AppError, Result};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+tokio::task_local! {
+    static CURRENT: CancelToken;
+}
+
+/// Cancellation signal shared between an operation and whoever may abort it.
+#[derive(Clone)]
+pub struct CancelToken {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self {
+            sender: Arc::new(watch::channel(false).0),
+        }
+    }
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Resolve once the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.sender.subscribe();
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// The token of the operation the current task runs in, if any.
+    pub fn current() -> Option<CancelToken> {
+        CURRENT.try_with(|token| token.clone()).ok()
+    }
+}
+
+/// Run a step of the current operation under a deadline. The future is dropped when the
+/// deadline passes or the operation is cancelled, so children spawned with
+/// `kill_on_drop` go away with it.
+pub async fn guard<T, F>(operation: &str, timeout: Duration, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let token = CancelToken::current().unwrap_or_default();
+
+    tokio::select! {
+        result = future => result,
+        _ = tokio::time::sleep(timeout) => {
+            Err(AppError::Timeout(operation.to_string(), timeout.as_secs()))
+        }
+        _ = token.cancelled() => Err(AppError::Cancelled),
+    }
+}
+
+/// Sleep unless the current operation gets cancelled first.
+pub async fn sleep(duration: Duration) -> Result<()> {
+    let token = CancelToken::current().unwrap_or_default();
+
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => Ok(()),
+        _ = token.cancelled() => Err(AppError::Cancelled),
+    }
+}
+
+/// Long running operations the frontend can abort by id.
+#[derive(Default)]
+pub struct OperationRegistry {
+    tokens: Mutex<HashMap<String, CancelToken>>,
+}
+
+impl OperationRegistry {
+    /// Run `future` as a cancellable operation. Without an id it simply runs to completion.
+    pub async fn run<T, F>(&self, id: Option<String>, future: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let Some(id) = id else {
+            return future.await;
+        };
+
+        let token = CancelToken::default();
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(id.clone(), token.clone());
+
+        // Unregister even when the caller drops the future early
+        let _registration = Registration { registry: self, id };
+
+        CURRENT.scope(token, future).await
+    }
+
+    /// Cancel a running operation. Returns false when no operation has that id.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.tokens.lock().unwrap().get(id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+struct Registration<'a> {
+    registry: &'a OperationRegistry,
+    id: String,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.registry.tokens.lock().unwrap().remove(&self.id);
+    }
+}
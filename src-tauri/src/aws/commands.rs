@@ -1,16 +1,30 @@
-use super::aws_cli::AwsCli;
-use super::aws_config::AwsConfig;
+use super::aws_config::{AwsConfig, AwsProfile, ServicesSection, SsoSession};
+use super::backend::SharedBackend;
+use super::cancellation::OperationRegistry;
+use super::config_lint::{ConfigLint, ValidationReport};
+use super::credential_process::CredentialProcess;
+use super::credentials::CredentialManager;
+use super::derived_profile::DerivedProfile;
 use super::keyring_manager::KeyringManager;
 use super::mfa::MfaManager;
+use super::port_allocator::PortAllocator;
+use super::profile_cache::ProfileCache;
+use super::readiness::{wait_until_ready, Readiness};
 use super::session::SessionManager;
-use super::session_config::SessionConfigManager;
+use super::session_config::{SessionConfigManager, SessionOverrides};
+use super::session_log::LogLine;
+use super::session_registry::{ActiveSession, SessionRegistry};
+use super::settings::AwsSettings;
+use super::supervisor::SessionSupervisor;
+use super::tooling::{Tooling, ToolingReport};
 use super::utils::*;
 use crate::{
     error::{AppError, Result},
     util::formatter::log_time_fmt,
 };
 use serde::{Deserialize, Serialize};
-use tauri::command;
+use std::time::Duration;
+use tauri::{command, AppHandle, State};
 
 #[derive(Serialize, Deserialize)]
 pub struct ProfileInfo {
@@ -18,7 +32,11 @@ pub struct ProfileInfo {
     pub region: Option<String>,
     pub has_mfa: bool,
     pub has_config: bool,
+    pub forwards: Vec<String>,
     pub mfa_serial: Option<String>,
+    pub role_arn: Option<String>,
+    pub source_profile: Option<String>,
+    pub sso: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -29,16 +47,22 @@ pub struct ProfileListResponse {
 
 #[derive(Serialize, Deserialize)]
 pub struct SessionConfig {
+    pub forward: Option<String>,
     pub target: String,
     pub local_port: u16,
     pub remote_port: u16,
     pub document_name: String,
+    pub host: Option<String>,
+    pub auto_reconnect: bool,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ConfigResponse {
     pub profile: String,
     pub config: Option<SessionConfig>,
+    pub forwards: Vec<String>,
     pub config_path: String,
     pub updated: bool,
 }
@@ -66,13 +90,72 @@ pub struct MfaSetupResponse {
     pub imported: bool,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectStatus {
+    Ready,
+    Failed,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ConnectResponse {
     pub profile: String,
+    pub forward: Option<String>,
     pub target: String,
     pub local_port: u16,
     pub remote_port: u16,
     pub document: String,
+    pub host: Option<String>,
+    pub expiration: String,
+    pub using_cached: bool,
+    pub session_id: Option<u64>,
+    pub status: ConnectStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: u64,
+    pub profile: String,
+    pub forward: Option<String>,
+    pub target: String,
+    pub local_port: u16,
+    pub remote_port: u16,
+    pub document: String,
+    pub host: Option<String>,
+    pub pid: Option<u32>,
+    pub started_at: String,
+    pub expiration: String,
+    pub auto_reconnect: bool,
+    pub reconnects: u32,
+}
+
+impl From<ActiveSession> for SessionInfo {
+    fn from(session: ActiveSession) -> Self {
+        Self {
+            id: session.id,
+            profile: session.profile,
+            forward: session.config.forward,
+            target: session.config.target,
+            local_port: session.config.local_port,
+            remote_port: session.config.remote_port,
+            document: session.config.document_name,
+            host: session.config.host,
+            pid: session.pid,
+            started_at: session.started_at.format(log_time_fmt()).unwrap(),
+            expiration: session.expiration.format(log_time_fmt()).unwrap(),
+            auto_reconnect: session.config.auto_reconnect,
+            reconnects: session.reconnects,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShellResponse {
+    pub profile: String,
+    pub target: String,
+    pub command: Option<String>,
+    pub pid: Option<u32>,
     pub expiration: String,
     pub using_cached: bool,
 }
@@ -83,6 +166,43 @@ pub struct CodeResponse {
     pub ttl: u64,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct CredentialProcessResponse {
+    pub profile: String,
+    pub target_profile: String,
+    pub command: String,
+    pub config_path: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DerivedProfileResponse {
+    pub profile: String,
+    pub derived_profile: String,
+    pub expiration: String,
+    pub credentials_path: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MigrationResponse {
+    pub config_path: String,
+    /// Where the ini file was moved, or `None` when there was nothing to migrate.
+    pub backup_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CancelResponse {
+    pub operation_id: String,
+    pub cancelled: bool,
+}
+
+#[derive(Serialize)]
+pub struct ProfileDetailsResponse {
+    pub profile: AwsProfile,
+    pub sso_session: Option<SsoSession>,
+    pub services: Option<ServicesSection>,
+    pub has_mfa_secret: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct RemoveResponse {
     pub profile: String,
@@ -90,36 +210,69 @@ pub struct RemoveResponse {
 }
 
 #[command]
-pub async fn list_aws_profiles() -> Result<ProfileListResponse> {
+pub async fn list_aws_profiles(
+    backend: State<'_, SharedBackend>,
+) -> Result<ProfileListResponse> {
+    collect_profiles(backend.inner().clone(), false).await
+}
+
+/// List the profiles after fetching identity details from AWS again, bypassing the cache.
+#[command]
+pub async fn refresh_aws_profiles(
+    backend: State<'_, SharedBackend>,
+) -> Result<ProfileListResponse> {
+    collect_profiles(backend.inner().clone(), true).await
+}
+
+async fn collect_profiles(backend: SharedBackend, force: bool) -> Result<ProfileListResponse> {
     let profiles = AwsConfig::list_profiles()?;
     let configured = SessionConfigManager::list_configured_profiles()?;
 
-    let mut profile_infos = Vec::new();
-
-    for profile in profiles {
-        let has_mfa = KeyringManager::has_secret(&profile.name);
-        let has_config = configured.contains(&profile.name);
+    // Role profiles use the MFA device of their source profile
+    let mfa_owner = |profile: &AwsProfile| match (&profile.role_arn, &profile.source_profile) {
+        (Some(_), Some(source)) => source.clone(),
+        _ => profile.name.clone(),
+    };
 
-        let mfa_serial = if has_mfa {
-            if let Ok(username) = AwsConfig::get_username(&profile.name).await {
-                MfaManager::fetch_mfa_serial(&username, &profile.name)
-                    .await
-                    .ok()
+    let lookups = profiles
+        .iter()
+        .filter(|profile| !profile.is_role())
+        .map(|profile| profile.name.clone())
+        .filter(|name| KeyringManager::has_secret(name))
+        .collect::<Vec<_>>();
+    let details = ProfileCache::lookup(backend, lookups, force).await;
+
+    let profile_infos = profiles
+        .into_iter()
+        .map(|profile| {
+            let owner = mfa_owner(&profile);
+            let has_mfa =
+                KeyringManager::has_secret(&profile.name) || KeyringManager::has_secret(&owner);
+            let has_config = SessionConfigManager::is_configured(&configured, &profile.name);
+            let forwards = SessionConfigManager::forward_names(&configured, &profile.name);
+            let mfa_serial = if has_mfa {
+                profile.mfa_serial.clone().or_else(|| {
+                    details
+                        .get(&owner)
+                        .and_then(|cached| cached.mfa_serial.clone())
+                })
             } else {
                 None
+            };
+
+            ProfileInfo {
+                sso: profile.is_sso(),
+                name: profile.name,
+                region: profile.region,
+                has_mfa,
+                has_config,
+                forwards,
+                mfa_serial,
+                role_arn: profile.role_arn,
+                source_profile: profile.source_profile,
             }
-        } else {
-            None
-        };
-
-        profile_infos.push(ProfileInfo {
-            name: profile.name,
-            region: profile.region,
-            has_mfa,
-            has_config,
-            mfa_serial,
-        });
-    }
+        })
+        .collect();
 
     Ok(ProfileListResponse {
         profiles: profile_infos,
@@ -127,36 +280,76 @@ pub async fn list_aws_profiles() -> Result<ProfileListResponse> {
     })
 }
 
+/// Everything the shared config says about a profile, including the sections it refers to.
+#[command]
+pub async fn get_profile_details(profile: String) -> Result<ProfileDetailsResponse> {
+    let profile = AwsConfig::get_profile(&profile)?;
+
+    let sso_session = match &profile.sso_session {
+        Some(name) => AwsConfig::sso_session(name)?,
+        None => None,
+    };
+    let services = match &profile.services {
+        Some(name) => AwsConfig::services(name)?,
+        None => None,
+    };
+
+    Ok(ProfileDetailsResponse {
+        has_mfa_secret: KeyringManager::has_secret(&profile.name),
+        profile,
+        sso_session,
+        services,
+    })
+}
+
 #[command]
+#[allow(clippy::too_many_arguments)]
 pub async fn show_aws_config(
     profile: String,
+    forward: Option<String>,
     target: Option<String>,
     local_port: Option<u16>,
     remote_port: Option<u16>,
     document: Option<String>,
+    host: Option<String>,
+    auto_reconnect: Option<bool>,
 ) -> Result<ConfigResponse> {
     if !AwsConfig::profile_exists(&profile) {
         return Err(AppError::ProfileNotFound(profile));
     }
 
-    let updated =
-        target.is_some() || local_port.is_some() || remote_port.is_some() || document.is_some();
+    let overrides = SessionOverrides {
+        target,
+        local_port,
+        remote_port,
+        document_name: document,
+        host,
+        auto_reconnect,
+    };
+    let updated = !overrides.is_empty();
 
     if updated {
-        SessionConfigManager::update_config(&profile, target, local_port, remote_port, document)?;
+        SessionConfigManager::update_config(&profile, forward.as_deref(), overrides)?;
     }
 
     let config_path = get_aws_sessions_path()?;
-    let config = SessionConfigManager::load_config(&profile)?;
+    let config = SessionConfigManager::load_config(&profile, forward.as_deref())?;
+    let forwards = SessionConfigManager::list_forwards(&profile)?;
 
     Ok(ConfigResponse {
         profile,
         config: config.map(|c| SessionConfig {
+            forward: c.forward,
             target: c.target,
             local_port: c.local_port,
             remote_port: c.remote_port,
             document_name: c.document_name,
+            host: c.host,
+            auto_reconnect: c.auto_reconnect,
+            description: c.description,
+            tags: c.tags,
         }),
+        forwards,
         config_path: config_path.display().to_string(),
         updated,
     })
@@ -171,38 +364,50 @@ pub async fn init_aws_configs() -> Result<ConfigResponse> {
     Ok(ConfigResponse {
         profile: String::new(),
         config: None,
+        forwards: Vec::new(),
         config_path: config_path.display().to_string(),
         updated: true,
     })
 }
 
+/// Convert an ini sessions file of earlier versions to the versioned format.
+#[command]
+pub async fn migrate_sessions() -> Result<MigrationResponse> {
+    let backup_path = SessionConfigManager::migrate()?;
+
+    Ok(MigrationResponse {
+        config_path: get_aws_sessions_path()?.display().to_string(),
+        backup_path: backup_path.map(|path| path.display().to_string()),
+    })
+}
+
 #[command]
-pub async fn check_mfa_status(profile: String) -> Result<StatusResponse> {
+pub async fn check_mfa_status(
+    backend: State<'_, SharedBackend>,
+    profile: String,
+) -> Result<StatusResponse> {
     if !AwsConfig::profile_exists(&profile) {
         return Err(AppError::ProfileNotFound(profile));
     }
 
-    let has_mfa_secret = KeyringManager::has_secret(&profile);
+    let backend = backend.as_ref();
+    let mfa_profile = AwsConfig::mfa_profile(&profile)?;
+    let has_mfa_secret =
+        KeyringManager::has_secret(&profile) || KeyringManager::has_secret(&mfa_profile);
 
-    let (identity, mfa_device) = match AwsCli::get_caller_identity(Some(&profile)).await {
+    let (identity, mfa_device) = match backend.get_caller_identity(Some(&mfa_profile)).await {
         Ok(identity_data) => {
-            let account = identity_data["Account"]
-                .as_str()
-                .unwrap_or("Unknown")
-                .to_string();
-            let arn = identity_data["Arn"]
-                .as_str()
-                .unwrap_or("Unknown")
-                .to_string();
-            let username = arn.split('/').last().unwrap_or(&arn).to_string();
-
-            let mfa_serial = MfaManager::fetch_mfa_serial(&username, &profile).await.ok();
+            let username = identity_data.username();
+
+            let mfa_serial = MfaManager::fetch_mfa_serial(backend, &username, &mfa_profile)
+                .await
+                .ok();
 
             (
                 Some(IdentityInfo {
-                    account,
-                    username: username.to_string(),
-                    arn,
+                    account: identity_data.account,
+                    username,
+                    arn: identity_data.arn,
                 }),
                 mfa_serial,
             )
@@ -220,29 +425,28 @@ pub async fn check_mfa_status(profile: String) -> Result<StatusResponse> {
 
 #[command]
 pub async fn setup_mfa_device(
+    backend: State<'_, SharedBackend>,
+    operations: State<'_, OperationRegistry>,
     profile: String,
     import_qr: Option<String>,
+    operation_id: Option<String>,
 ) -> Result<MfaSetupResponse> {
-    if !check_aws_cli() {
-        return Err(AppError::Custom("AWS CLI not found".to_string()));
-    }
-
     if !AwsConfig::profile_exists(&profile) {
         return Err(AppError::ProfileNotFound(profile));
     }
 
-    let username = AwsConfig::get_username(&profile).await?;
-
-    let (serial, imported) = if let Some(qr_path) = import_qr {
-        let secret = MfaManager::import_qr_code(&qr_path)?;
-        let serial = MfaManager::fetch_mfa_serial(&username, &profile).await?;
-        KeyringManager::store_secret(&profile, &secret)?;
-        (serial, true)
-    } else {
-        let (serial, secret) = MfaManager::setup_mfa_device(&username, &profile).await?;
-        KeyringManager::store_secret(&profile, &secret)?;
-        (serial, false)
-    };
+    let backend = backend.as_ref();
+    // The device of a role profile belongs to the user of its source profile
+    let mfa_profile = AwsConfig::mfa_profile(&profile)?;
+
+    let (serial, imported) = operations
+        .run(
+            operation_id,
+            MfaManager::register(backend, &mfa_profile, import_qr.as_deref()),
+        )
+        .await?;
+
+    let _ = ProfileCache::invalidate(Some(&mfa_profile));
 
     Ok(MfaSetupResponse {
         success: true,
@@ -253,60 +457,210 @@ pub async fn setup_mfa_device(
 }
 
 #[command]
+pub async fn cancel_operation(
+    operations: State<'_, OperationRegistry>,
+    operation_id: String,
+) -> Result<CancelResponse> {
+    let cancelled = operations.cancel(&operation_id);
+
+    Ok(CancelResponse {
+        operation_id,
+        cancelled,
+    })
+}
+
+#[command]
+#[allow(clippy::too_many_arguments)]
 pub async fn connect(
+    app: AppHandle,
+    backend: State<'_, SharedBackend>,
+    registry: State<'_, SessionRegistry>,
     profile: String,
+    forward: Option<String>,
     target: Option<String>,
     port: Option<u16>,
     remote_port: Option<u16>,
     document: Option<String>,
+    host: Option<String>,
+    auto_reconnect: Option<bool>,
+    auto_port: Option<bool>,
+    timeout: Option<u64>,
 ) -> Result<ConnectResponse> {
-    if !check_aws_cli() {
-        return Err(AppError::Custom("AWS CLI not found".to_string()));
-    }
+    let settings = AwsSettings::load();
+    Tooling::ensure_ready(&settings).await?;
 
     if !AwsConfig::profile_exists(&profile) {
         return Err(AppError::ProfileNotFound(profile));
     }
 
-    let config =
-        SessionConfigManager::resolve_config(&profile, target, port, remote_port, document)?;
-    let username = AwsConfig::get_username(&profile).await?;
-    let serial = MfaManager::fetch_mfa_serial(&username, &profile).await?;
-
-    let (credentials, using_cached) = match KeyringManager::get_session_credentials(&profile) {
-        Ok(cached_creds) if cached_creds.is_valid() => (cached_creds, true),
-        _ => {
-            let secret = KeyringManager::get_secret(&profile)?;
-            let token_code = MfaManager::generate_totp_code(&secret)?;
-            let credentials =
-                AwsCli::get_session_token(&serial, &token_code, Some(&profile)).await?;
-
-            let _ = KeyringManager::store_session_credentials(&profile, &credentials);
-            (credentials, false)
-        }
+    let overrides = SessionOverrides {
+        target,
+        local_port: port,
+        remote_port,
+        document_name: document,
+        host,
+        auto_reconnect,
     };
+    let mut config =
+        SessionConfigManager::resolve_config(&profile, forward.as_deref(), overrides)?;
 
-    let _child = SessionManager::start_session(
-        &profile,
-        &config.target,
+    let port_key = SessionConfigManager::section_name(&profile, forward.as_deref());
+    let auto_port = auto_port.unwrap_or(settings.auto_port);
+
+    if auto_port {
+        config.local_port = PortAllocator::allocate(&port_key, config.local_port, &settings)?;
+    }
+
+    let (credentials, using_cached) = CredentialManager::resolve(backend.as_ref(), &profile).await?;
+
+    if settings.write_derived_credentials {
+        if let Err(e) = DerivedProfile::write(&profile, &credentials) {
+            log::warn!("Failed to write derived credentials for '{}': {}", profile, e);
+        }
+    }
+
+    let child = SessionManager::start_session(&profile, &config, &credentials).await?;
+
+    let session = registry
+        .register(&app, &profile, &config, credentials.expiration, child)
+        .await;
+
+    let timeout = timeout.unwrap_or(settings.ready_timeout_secs);
+    let readiness = wait_until_ready(
+        &registry,
+        session.id,
         config.local_port,
-        config.remote_port,
-        &config.document_name,
-        &credentials,
+        Duration::from_secs(timeout),
     )
-    .await?;
+    .await;
+
+    let (session_id, status, error) = match readiness {
+        Readiness::Ready => {
+            if auto_port {
+                let _ = PortAllocator::remember(&port_key, config.local_port);
+            }
+            if config.auto_reconnect {
+                SessionSupervisor::spawn(app, session.id);
+            }
+            (Some(session.id), ConnectStatus::Ready, None)
+        }
+        Readiness::Failed(error) => {
+            let _ = registry.stop(session.id).await;
+            CredentialManager::handle_error(&profile, &AppError::AwsCli(error.clone()));
+            (None, ConnectStatus::Failed, Some(error))
+        }
+    };
 
     Ok(ConnectResponse {
         profile,
+        forward: config.forward,
         target: config.target,
         local_port: config.local_port,
         remote_port: config.remote_port,
         document: config.document_name,
+        host: config.host,
+        expiration: credentials.expiration.format(log_time_fmt()).unwrap(),
+        using_cached,
+        session_id,
+        status,
+        error,
+    })
+}
+
+#[command]
+pub async fn open_shell(
+    backend: State<'_, SharedBackend>,
+    profile: String,
+    forward: Option<String>,
+    target: Option<String>,
+    command: Option<String>,
+) -> Result<ShellResponse> {
+    let settings = AwsSettings::load();
+    Tooling::ensure_ready(&settings).await?;
+
+    if !AwsConfig::profile_exists(&profile) {
+        return Err(AppError::ProfileNotFound(profile));
+    }
+
+    let overrides = SessionOverrides {
+        target,
+        ..Default::default()
+    };
+    let config = SessionConfigManager::resolve_config(&profile, forward.as_deref(), overrides)?;
+
+    let (credentials, using_cached) = CredentialManager::resolve(backend.as_ref(), &profile).await?;
+
+    let pid = SessionManager::start_shell(
+        &profile,
+        &config.target,
+        command.as_deref(),
+        settings.terminal.as_deref(),
+        &credentials,
+    )?;
+
+    Ok(ShellResponse {
+        profile,
+        target: config.target,
+        command,
+        pid,
         expiration: credentials.expiration.format(log_time_fmt()).unwrap(),
         using_cached,
     })
 }
 
+/// Expose a profile's MFA session credentials to other tools through `credential_process`.
+#[command]
+pub async fn install_credential_process(
+    profile: String,
+    target_profile: Option<String>,
+) -> Result<CredentialProcessResponse> {
+    if !AwsConfig::profile_exists(&profile) {
+        return Err(AppError::ProfileNotFound(profile));
+    }
+
+    let (target_profile, command) =
+        CredentialProcess::install(&profile, target_profile.as_deref())?;
+
+    Ok(CredentialProcessResponse {
+        profile,
+        target_profile,
+        command,
+        config_path: get_aws_config_path()?.display().to_string(),
+    })
+}
+
+/// Write a profile's MFA session credentials into `~/.aws/credentials` as a derived profile.
+#[command]
+pub async fn activate_derived_profile(
+    backend: State<'_, SharedBackend>,
+    profile: String,
+) -> Result<DerivedProfileResponse> {
+    if !AwsConfig::profile_exists(&profile) {
+        return Err(AppError::ProfileNotFound(profile));
+    }
+
+    let (credentials, _) = CredentialManager::resolve(backend.as_ref(), &profile).await?;
+    let derived_profile = DerivedProfile::write(&profile, &credentials)?;
+
+    Ok(DerivedProfileResponse {
+        profile,
+        derived_profile,
+        expiration: credentials.expiration.format(log_time_fmt()).unwrap(),
+        credentials_path: get_aws_credentials_path()?.display().to_string(),
+    })
+}
+
+/// Check the sessions file against the AWS profiles and report problems with suggested fixes.
+#[command]
+pub async fn validate_configuration() -> Result<ValidationReport> {
+    ConfigLint::run()
+}
+
+#[command]
+pub async fn check_tooling() -> Result<ToolingReport> {
+    Ok(Tooling::probe(&AwsSettings::load()).await)
+}
+
 #[command]
 pub async fn generate_totp_code(profile: String) -> Result<CodeResponse> {
     if !AwsConfig::profile_exists(&profile) {
@@ -328,6 +682,8 @@ pub async fn remove_aws_profile(profile: String) -> Result<RemoveResponse> {
 
     KeyringManager::delete_secret(&profile)?;
     let _ = KeyringManager::delete_session_credentials(&profile);
+    let _ = ProfileCache::invalidate(Some(&profile));
+    let _ = DerivedProfile::remove(&profile);
     SessionConfigManager::remove_config(&profile)?;
 
     Ok(RemoveResponse {
@@ -344,6 +700,7 @@ pub async fn remove_mfa_device(profile: String) -> Result<RemoveResponse> {
 
     KeyringManager::delete_secret(&profile)?;
     let _ = KeyringManager::delete_session_credentials(&profile);
+    let _ = ProfileCache::invalidate(Some(&profile));
 
     Ok(RemoveResponse {
         profile,
@@ -356,3 +713,31 @@ pub async fn get_profile_names() -> Result<Vec<String>> {
     let profiles = AwsConfig::list_profiles()?;
     Ok(profiles.iter().map(|p| p.name.clone()).collect())
 }
+
+#[command]
+pub async fn list_sessions(registry: State<'_, SessionRegistry>) -> Result<Vec<SessionInfo>> {
+    let sessions = registry.list().await;
+    Ok(sessions.into_iter().map(SessionInfo::from).collect())
+}
+
+#[command]
+pub async fn stop_session(registry: State<'_, SessionRegistry>, id: u64) -> Result<SessionInfo> {
+    let session = registry.stop(id).await?;
+    Ok(SessionInfo::from(session))
+}
+
+#[command]
+pub async fn stop_all_sessions(registry: State<'_, SessionRegistry>) -> Result<Vec<SessionInfo>> {
+    let sessions = registry.stop_all().await;
+    Ok(sessions.into_iter().map(SessionInfo::from).collect())
+}
+
+#[command]
+pub async fn get_session_log(
+    registry: State<'_, SessionRegistry>,
+    id: u64,
+    tail: Option<usize>,
+) -> Result<Vec<LogLine>> {
+    let log = registry.log(id).await?;
+    Ok(log.lines(tail))
+}
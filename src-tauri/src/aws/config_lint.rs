@@ -0,0 +1,561 @@
+use super::aws_config::AwsConfig;
+use super::keyring_manager::KeyringManager;
+use super::session_config::{SessionConfig, SessionConfigManager, StoredSessions};
+use super::sessions_file::{locate, InvalidValue, SessionDefinition, SessionsFile};
+use super::utils::get_aws_sessions_path;
+use crate::error::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The session cannot be used as configured.
+    Error,
+    /// The session works but is likely to fail or clash at connect time.
+    Warning,
+    Info,
+}
+
+/// One problem in the configuration, with what to do about it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub severity: Severity,
+    /// The session concerned, as `profile` or `profile.forward`.
+    pub session: Option<String>,
+    /// Line in the sessions file, when known.
+    pub line: Option<usize>,
+    pub message: String,
+    pub suggestion: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub config_path: String,
+    pub findings: Vec<Finding>,
+    /// Whether there are no errors. Warnings do not prevent connecting.
+    pub valid: bool,
+}
+
+/// A session of the file with the keys leading to it, for line lookups.
+struct SessionEntry<'a> {
+    name: String,
+    keys: Vec<String>,
+    definition: &'a SessionDefinition,
+}
+
+/// A sessions file as read for linting.
+struct ReadSessions {
+    file: SessionsFile,
+    /// The JSON text, for line lookups. `None` for an ini file.
+    content: Option<String>,
+    /// Values left out of `file` because they could not be read.
+    invalid: Vec<InvalidValue>,
+}
+
+/// Checks the sessions file against the AWS profiles, to catch mistakes before connecting.
+pub struct ConfigLint;
+
+impl ConfigLint {
+    pub fn run() -> Result<ValidationReport> {
+        let config_path = get_aws_sessions_path()?;
+        let findings = Self::lint(SessionConfigManager::stored()?, &config_path)?;
+
+        Ok(ValidationReport {
+            config_path: config_path.display().to_string(),
+            valid: !findings.iter().any(|f| f.severity == Severity::Error),
+            findings,
+        })
+    }
+
+    fn lint(stored: StoredSessions, config_path: &Path) -> Result<Vec<Finding>> {
+        let mut findings = Vec::new();
+
+        let profiles = match AwsConfig::list_profiles() {
+            Ok(profiles) => Some(profiles),
+            Err(e) => {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    session: None,
+                    line: None,
+                    message: e.to_string(),
+                    suggestion: "Run 'aws configure' to create a profile".to_string(),
+                });
+                None
+            }
+        };
+        let names: Vec<String> = profiles.iter().flatten().map(|p| p.name.clone()).collect();
+
+        let read = Self::read_sessions(stored, config_path, &names, &mut findings)?;
+
+        if let Some(read) = &read {
+            let file = &read.file;
+            let line = |keys: &[String]| read.content.as_deref().map(|c| locate(c, keys));
+            let sessions = Self::sessions(file);
+
+            for value in &read.invalid {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    session: Some(value.session.clone()),
+                    line: Some(value.line),
+                    message: value.message.clone(),
+                    suggestion: "Correct the value, or remove the key to use the default"
+                        .to_string(),
+                });
+            }
+
+            // The ini reader reports these along with the invalid values
+            if read.content.is_some() {
+                for (keys, message) in file.problems() {
+                    findings.push(Finding {
+                        severity: Severity::Error,
+                        session: session_of(&keys),
+                        line: line(&keys),
+                        message: format!("{}: {}", keys.join("."), message),
+                        suggestion: "Correct the value, or remove the key to use the default"
+                            .to_string(),
+                    });
+                }
+            }
+
+            // A local port that could not be read is reported already, not as the default
+            let unread_ports: Vec<&str> = read
+                .invalid
+                .iter()
+                .filter(|value| value.key == "local_port")
+                .map(|value| value.session.as_str())
+                .collect();
+
+            Self::check_missing_targets(file, &line, &mut findings);
+            Self::check_targets(&sessions, &line, &mut findings);
+            Self::check_ports(&sessions, &unread_ports, &line, &mut findings);
+
+            if let Some(profiles) = &profiles {
+                for profile in file.profiles.keys() {
+                    if profiles.iter().any(|p| &p.name == profile) {
+                        continue;
+                    }
+                    findings.push(Finding {
+                        severity: Severity::Warning,
+                        session: Some(profile.clone()),
+                        line: line(&["profiles".to_string(), profile.clone()]),
+                        message: format!("No AWS profile named '{}'", profile),
+                        suggestion: "Rename the entry to an existing profile or remove it"
+                            .to_string(),
+                    });
+                }
+            }
+        }
+
+        for profile in profiles.iter().flatten() {
+            let configured = read
+                .as_ref()
+                .and_then(|read| read.file.profiles.get(&profile.name))
+                .is_some_and(|d| d.target.is_some() || !d.forwards.is_empty());
+
+            if !configured && KeyringManager::has_secret(&profile.name) {
+                findings.push(Finding {
+                    severity: Severity::Info,
+                    session: Some(profile.name.clone()),
+                    line: None,
+                    message: format!(
+                        "Profile '{}' has an MFA secret but no session configured",
+                        profile.name
+                    ),
+                    suggestion: "Add a target for it to the sessions file".to_string(),
+                });
+            }
+        }
+
+        findings.sort_by_key(|finding| finding.severity);
+
+        Ok(findings)
+    }
+
+    /// The sessions file, without migrating the ini file of earlier versions. Invalid ports
+    /// are left out of it so the other checks still run. A file that cannot be read is
+    /// reported as a finding.
+    fn read_sessions(
+        stored: StoredSessions,
+        config_path: &Path,
+        profiles: &[String],
+        findings: &mut Vec<Finding>,
+    ) -> Result<Option<ReadSessions>> {
+        let result = match stored {
+            StoredSessions::Current { path, content } => {
+                SessionsFile::parse_lenient(&content, &path.display().to_string()).map(
+                    |(file, invalid)| ReadSessions {
+                        file,
+                        content: Some(content),
+                        invalid,
+                    },
+                )
+            }
+            StoredSessions::Legacy { path, document } => {
+                findings.push(Finding {
+                    severity: Severity::Info,
+                    session: None,
+                    line: None,
+                    message: format!("{} uses the ini format", path.display()),
+                    suggestion: format!(
+                        "Run migrate_sessions to convert it to {}, the original is kept as a backup",
+                        config_path.display()
+                    ),
+                });
+
+                SessionsFile::from_ini_lenient(&document, &path.display().to_string(), profiles)
+                    .map(|(file, invalid)| ReadSessions {
+                        file,
+                        content: None,
+                        invalid,
+                    })
+            }
+            StoredSessions::Missing => {
+                findings.push(Finding {
+                    severity: Severity::Info,
+                    session: None,
+                    line: None,
+                    message: "No sessions are configured".to_string(),
+                    suggestion: "Create the sessions file from your profiles with init".to_string(),
+                });
+                return Ok(None);
+            }
+        };
+
+        match result {
+            Ok(read) => Ok(Some(read)),
+            Err(AppError::InvalidConfig(source, line, message)) => {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    session: None,
+                    line: Some(line),
+                    message: format!("{}: {}", source, message),
+                    suggestion: "Fix the file at the reported line; the remaining session \
+                                 checks run once it parses"
+                        .to_string(),
+                });
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn sessions(file: &SessionsFile) -> Vec<SessionEntry<'_>> {
+        let mut sessions = Vec::new();
+
+        for (profile, definition) in &file.profiles {
+            let keys = vec!["profiles".to_string(), profile.clone()];
+
+            if definition.target.is_some() {
+                sessions.push(SessionEntry {
+                    name: profile.clone(),
+                    keys: keys.clone(),
+                    definition,
+                });
+            }
+
+            for (forward, definition) in &definition.forwards {
+                sessions.push(SessionEntry {
+                    name: SessionConfigManager::section_name(profile, Some(forward)),
+                    keys: [keys.clone(), vec!["forwards".to_string(), forward.clone()]].concat(),
+                    definition,
+                });
+            }
+        }
+
+        sessions
+    }
+
+    /// Profiles without a target, such as the placeholders written by init. A profile that
+    /// only groups forwards needs none, and forwards without one are in the file problems.
+    fn check_missing_targets(
+        file: &SessionsFile,
+        line: &impl Fn(&[String]) -> Option<usize>,
+        findings: &mut Vec<Finding>,
+    ) {
+        for (profile, definition) in &file.profiles {
+            if definition.target.is_some() || !definition.forwards.is_empty() {
+                continue;
+            }
+
+            findings.push(Finding {
+                severity: Severity::Error,
+                session: Some(profile.clone()),
+                line: line(&["profiles".to_string(), profile.clone()]),
+                message: format!("Session '{}' has no target", profile),
+                suggestion: "Set target to the ID of the instance to connect to, or remove the \
+                             entry"
+                    .to_string(),
+            });
+        }
+    }
+
+    fn check_targets(
+        sessions: &[SessionEntry],
+        line: &impl Fn(&[String]) -> Option<usize>,
+        findings: &mut Vec<Finding>,
+    ) {
+        for session in sessions {
+            let Some(target) = &session.definition.target else {
+                continue;
+            };
+            // Blank targets are among the file problems
+            if target.trim().is_empty() || is_instance_id(target) {
+                continue;
+            }
+
+            findings.push(Finding {
+                severity: Severity::Warning,
+                session: Some(session.name.clone()),
+                line: line(&[session.keys.clone(), vec!["target".to_string()]].concat()),
+                message: format!(
+                    "Target '{}' is not an instance ID (i-...) or managed instance ID (mi-...)",
+                    target
+                ),
+                suggestion: "Copy the ID from the EC2 or Systems Manager console".to_string(),
+            });
+        }
+    }
+
+    fn check_ports(
+        sessions: &[SessionEntry],
+        unread_ports: &[&str],
+        line: &impl Fn(&[String]) -> Option<usize>,
+        findings: &mut Vec<Finding>,
+    ) {
+        let default_port = SessionConfig::default().local_port;
+        let mut by_port: BTreeMap<u16, Vec<&SessionEntry>> = BTreeMap::new();
+
+        for session in sessions {
+            if unread_ports.contains(&session.name.as_str()) {
+                continue;
+            }
+
+            let local_port = session.definition.local_port.unwrap_or(default_port);
+            by_port.entry(local_port).or_default().push(session);
+
+            if (1..1024).contains(&local_port) {
+                findings.push(Finding {
+                    severity: Severity::Warning,
+                    session: Some(session.name.clone()),
+                    line: line(&[session.keys.clone(), vec!["local_port".to_string()]].concat()),
+                    message: format!(
+                        "Local port {} is privileged and needs administrator rights",
+                        local_port
+                    ),
+                    suggestion: "Use a local port of 1024 or above".to_string(),
+                });
+            }
+        }
+
+        for (port, sessions) in by_port.iter().filter(|(_, s)| s.len() > 1) {
+            let names: Vec<&str> = sessions.iter().map(|s| s.name.as_str()).collect();
+
+            for session in sessions {
+                findings.push(Finding {
+                    severity: Severity::Warning,
+                    session: Some(session.name.clone()),
+                    line: line(&[session.keys.clone(), vec!["local_port".to_string()]].concat()),
+                    message: format!(
+                        "Local port {} is shared by {}, which cannot run at the same time",
+                        port,
+                        names.join(", ")
+                    ),
+                    suggestion: "Give each session its own local_port, or enable auto_port"
+                        .to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// The session a problem of the file was found in, from the keys leading to it.
+fn session_of(keys: &[String]) -> Option<String> {
+    match keys {
+        [_, profile, _, forward, ..] => {
+            Some(SessionConfigManager::section_name(profile, Some(forward)))
+        }
+        [_, profile, ..] => Some(profile.clone()),
+        _ => None,
+    }
+}
+
+/// EC2 instance IDs are `i-` with 8 or 17 hex digits, managed instances `mi-` with 17.
+fn is_instance_id(target: &str) -> bool {
+    let is_hex = |id: &str, lengths: &[usize]| {
+        lengths.contains(&id.len()) && id.chars().all(|c| c.is_ascii_hexdigit())
+    };
+
+    match (target.strip_prefix("i-"), target.strip_prefix("mi-")) {
+        (Some(id), _) => is_hex(id, &[8, 17]),
+        (_, Some(id)) => is_hex(id, &[17]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::fake_backend::use_offline_environment;
+    use crate::aws::ini_document::IniDocument;
+    use std::path::PathBuf;
+
+    const SESSIONS: &str = r#"{
+  "version": 1,
+  "profiles": {
+    "dev": {
+      "target": "i-0123456789abcdef0",
+      "local_port": 70000,
+      "forwards": {
+        "db": { "target": "i-0123456789abcdef0", "local_port": 15432, "remote_port": -1 },
+        "cache": { "target": "bastion", "local_port": 15432 }
+      }
+    },
+    "ops": { "target": "i-0123456789abcdef0", "local_port": "13390" },
+    "ghost": { "target": "i-0123456789abcdef0", "local_port": 80 }
+  }
+}
+"#;
+
+    const LEGACY: &str = "\
+[dev]
+target = i-0123456789abcdef0
+local_port = 70000
+
+[dev.db]
+target = i-0123456789abcdef0
+local_port = abc
+remote_port = 0
+
+[ops]
+target = bastion
+";
+
+    fn lint(stored: StoredSessions) -> Vec<Finding> {
+        use_offline_environment();
+        ConfigLint::lint(stored, &PathBuf::from("sessions.json")).unwrap()
+    }
+
+    fn find<'a>(findings: &'a [Finding], session: &str, text: &str) -> &'a Finding {
+        findings
+            .iter()
+            .find(|f| f.session.as_deref() == Some(session) && f.message.contains(text))
+            .unwrap_or_else(|| {
+                panic!(
+                    "no finding for {} with '{}' in {:#?}",
+                    session, text, findings
+                )
+            })
+    }
+
+    #[test]
+    fn reports_every_bad_port_and_keeps_checking() {
+        let findings = lint(StoredSessions::Current {
+            path: PathBuf::from("sessions.json"),
+            content: SESSIONS.to_string(),
+        });
+
+        let port = find(&findings, "dev", "local_port 70000 is not a port");
+        assert_eq!((port.severity, port.line), (Severity::Error, Some(6)));
+        let port = find(&findings, "dev.db", "remote_port -1 is not a port");
+        assert_eq!((port.severity, port.line), (Severity::Error, Some(8)));
+        let port = find(&findings, "ops", "local_port \"13390\" is not a port");
+        assert_eq!((port.severity, port.line), (Severity::Error, Some(12)));
+
+        find(
+            &findings,
+            "dev.cache",
+            "Target 'bastion' is not an instance ID",
+        );
+        find(
+            &findings,
+            "dev.db",
+            "Local port 15432 is shared by dev.cache, dev.db",
+        );
+        find(&findings, "ghost", "Local port 80 is privileged");
+        find(&findings, "ghost", "No AWS profile named 'ghost'");
+
+        // Unread local ports are not taken for the default and reported as shared
+        assert!(!findings.iter().any(|f| f.message.contains("13389")));
+    }
+
+    #[test]
+    fn reports_every_bad_port_of_an_ini_file() {
+        let findings = lint(StoredSessions::Legacy {
+            path: PathBuf::from("sessions"),
+            document: IniDocument::parse(LEGACY),
+        });
+
+        assert!(findings
+            .iter()
+            .any(|f| f.severity == Severity::Info && f.message.contains("uses the ini format")));
+        let port = find(&findings, "dev", "local_port '70000' is not a valid port");
+        assert_eq!((port.severity, port.line), (Severity::Error, Some(3)));
+        let port = find(&findings, "dev.db", "local_port 'abc' is not a valid port");
+        assert_eq!(port.line, Some(7));
+        let port = find(
+            &findings,
+            "dev.db",
+            "remote_port must be between 1 and 65535",
+        );
+        assert_eq!(port.line, Some(8));
+        find(&findings, "ops", "Target 'bastion' is not an instance ID");
+    }
+
+    #[test]
+    fn reports_sessions_without_a_target() {
+        let content = r#"{
+  "version": 1,
+  "profiles": {
+    "dev": {},
+    "ops": { "target": " " },
+    "admin": {
+      "forwards": { "db": { "target": "i-0123456789abcdef0" } }
+    }
+  }
+}
+"#;
+        let findings = lint(StoredSessions::Current {
+            path: PathBuf::from("sessions.json"),
+            content: content.to_string(),
+        });
+
+        let missing = find(&findings, "dev", "Session 'dev' has no target");
+        assert_eq!((missing.severity, missing.line), (Severity::Error, Some(4)));
+        let blank = find(&findings, "ops", "target must not be empty");
+        assert_eq!((blank.severity, blank.line), (Severity::Error, Some(5)));
+        // A profile only grouping forwards needs no target
+        assert!(!findings
+            .iter()
+            .any(|f| f.session.as_deref() == Some("admin") && f.severity == Severity::Error));
+    }
+
+    #[test]
+    fn reports_placeholders_of_an_ini_file() {
+        let findings = lint(StoredSessions::Legacy {
+            path: PathBuf::from("sessions"),
+            document: IniDocument::parse("[dev]\ntarget =\nlocal_port =\n"),
+        });
+
+        let missing = find(&findings, "dev", "Session 'dev' has no target");
+        assert_eq!(missing.severity, Severity::Error);
+    }
+
+    #[test]
+    fn reports_a_file_that_does_not_parse() {
+        let findings = lint(StoredSessions::Current {
+            path: PathBuf::from("sessions.json"),
+            content:
+                "{\n  \"version\": 1,\n  \"profiles\": {\n    \"dev\": { \"tags\": 1 }\n  }\n}\n"
+                    .to_string(),
+        });
+
+        let error = findings
+            .iter()
+            .find(|f| f.severity == Severity::Error)
+            .unwrap();
+        assert_eq!(error.line, Some(4));
+        assert!(error.message.starts_with("sessions.json: invalid type"));
+    }
+}
@@ -0,0 +1,92 @@
+use super::backend::create_backend;
+use super::credentials::CredentialManager;
+use super::ini_document::IniDocument;
+use super::models::SessionCredentials;
+use super::utils::get_aws_config_path;
+use crate::error::{AppError, Result};
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+
+/// Argument selecting credential process mode, e.g. `akaw credential-process --profile dev`.
+pub const ENTRY_ARG: &str = "credential-process";
+const PROFILE_SUFFIX: &str = "-akaw";
+
+/// The `Version: 1` document `credential_process` providers print on stdout.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessCredentials<'a> {
+    version: u8,
+    access_key_id: &'a str,
+    secret_access_key: &'a str,
+    session_token: &'a str,
+    expiration: String,
+}
+
+impl<'a> ProcessCredentials<'a> {
+    fn new(credentials: &'a SessionCredentials) -> Result<Self> {
+        Ok(Self {
+            version: 1,
+            access_key_id: &credentials.access_key_id,
+            secret_access_key: &credentials.secret_access_key,
+            session_token: &credentials.session_token,
+            expiration: credentials
+                .expiration
+                .format(&Rfc3339)
+                .map_err(|e| AppError::Custom(e.to_string()))?,
+        })
+    }
+}
+
+/// Serves the cached MFA session credentials to other tools through `credential_process`.
+pub struct CredentialProcess;
+
+impl CredentialProcess {
+    /// Print the credentials of a profile, minting new ones when the cached set expired.
+    pub async fn run(profile: &str) -> Result<String> {
+        let backend = create_backend();
+        let (credentials, _) = CredentialManager::resolve(backend.as_ref(), profile).await?;
+
+        Ok(serde_json::to_string(&ProcessCredentials::new(
+            &credentials,
+        )?)?)
+    }
+
+    /// Point a profile of `~/.aws/config` at akaw. Unless named otherwise it goes into a derived
+    /// `<profile>-akaw` profile, since the source profile must keep its own keys for akaw to use.
+    pub fn install(profile: &str, target: Option<&str>) -> Result<(String, String)> {
+        let target = target
+            .map(|t| t.to_string())
+            .unwrap_or_else(|| format!("{}{}", profile, PROFILE_SUFFIX));
+
+        if target == profile {
+            return Err(AppError::Custom(
+                "The credential_process profile must differ from the source profile".to_string(),
+            ));
+        }
+
+        let command = Self::command(profile)?;
+        let section = if target == "default" {
+            target.clone()
+        } else {
+            format!("profile {}", target)
+        };
+
+        let config_path = get_aws_config_path()?;
+        let mut config = IniDocument::load(&config_path)?;
+        config.set(&section, "credential_process", &command);
+        config.save(&config_path)?;
+
+        Ok((target, command))
+    }
+
+    fn command(profile: &str) -> Result<String> {
+        let exe = std::env::current_exe()?.display().to_string();
+        let exe = if exe.contains(' ') {
+            format!("\"{}\"", exe)
+        } else {
+            exe
+        };
+
+        Ok(format!("{} {} --profile {}", exe, ENTRY_ARG, profile))
+    }
+}
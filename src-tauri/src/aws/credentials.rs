@@ -0,0 +1,264 @@
+use super::aws_config::{AwsConfig, AwsProfile};
+use super::aws_error::AwsErrorKind;
+use super::backend::AwsBackend;
+use super::cancellation;
+use super::keyring_manager::KeyringManager;
+use super::mfa::MfaManager;
+use super::models::{AssumeRoleRequest, SessionCredentials};
+use super::settings::AwsSettings;
+use super::sso::SsoManager;
+use crate::error::{AppError, Result};
+use std::future::Future;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+pub struct CredentialManager;
+
+impl CredentialManager {
+    /// Return the cached session credentials while they are valid, minting new ones otherwise.
+    /// The flag tells whether the cached credentials were used.
+    pub async fn resolve(
+        backend: &dyn AwsBackend,
+        profile: &str,
+    ) -> Result<(SessionCredentials, bool)> {
+        let lead = AwsSettings::load().refresh_lead();
+
+        match KeyringManager::get_session_credentials(profile) {
+            Ok(cached_creds) if cached_creds.is_valid(lead) => Ok((cached_creds, true)),
+            _ => Ok((Self::refresh(backend, profile).await?, false)),
+        }
+    }
+
+    /// Mint new MFA session credentials from the stored TOTP secret and cache them.
+    /// Role profiles assume their role with the MFA device of the source profile, SSO profiles
+    /// use the IAM Identity Center token and need no secret.
+    pub async fn refresh(backend: &dyn AwsBackend, profile: &str) -> Result<SessionCredentials> {
+        let aws_profile = AwsConfig::get_profile(profile)?;
+
+        let result = if aws_profile.is_sso() {
+            SsoManager::get_credentials(backend, &aws_profile).await
+        } else if aws_profile.is_role() {
+            Self::assume_role(backend, &aws_profile).await
+        } else {
+            Self::get_session_token(backend, profile).await
+        };
+
+        let credentials = result.inspect_err(|e| Self::handle_error(profile, e))?;
+
+        let _ = KeyringManager::store_session_credentials(profile, &credentials);
+        Ok(credentials)
+    }
+
+    async fn get_session_token(
+        backend: &dyn AwsBackend,
+        profile: &str,
+    ) -> Result<SessionCredentials> {
+        let serial = MfaManager::resolve_mfa_serial(backend, profile).await?;
+        let secret = KeyringManager::get_secret(profile)?;
+
+        let serial = &serial;
+        Self::with_totp(profile, &secret, |code| async move {
+            backend
+                .get_session_token(serial, &code, Some(profile))
+                .await
+        })
+        .await
+    }
+
+    async fn assume_role(
+        backend: &dyn AwsBackend,
+        role: &AwsProfile,
+    ) -> Result<SessionCredentials> {
+        let source = AwsConfig::mfa_profile(&role.name)?;
+
+        // A secret stored for the role itself wins over the one of its source profile
+        let secret = if KeyringManager::has_secret(&role.name) {
+            KeyringManager::get_secret(&role.name)?
+        } else {
+            KeyringManager::get_secret(&source)?
+        };
+
+        let serial = match &role.mfa_serial {
+            Some(serial) => serial.clone(),
+            None => MfaManager::resolve_mfa_serial(backend, &source).await?,
+        };
+
+        let request = AssumeRoleRequest {
+            role_arn: role.role_arn.clone().unwrap_or_default(),
+            role_session_name: role
+                .role_session_name
+                .clone()
+                .unwrap_or_else(|| format!("akaw-{}", OffsetDateTime::now_utc().unix_timestamp())),
+            serial_number: Some(serial),
+            token_code: None,
+            duration_seconds: role.duration_seconds,
+            external_id: role.external_id.clone(),
+        };
+
+        let (request, source) = (&request, source.as_str());
+        Self::with_totp(&role.name, &secret, |code| async move {
+            let request = AssumeRoleRequest {
+                token_code: Some(code),
+                ..request.clone()
+            };
+            backend.assume_role(&request, Some(source)).await
+        })
+        .await
+    }
+
+    /// Call AWS with the current TOTP code. A code can only be used once, so a rejected code
+    /// is retried with the one from the next window.
+    async fn with_totp<F, Fut>(
+        profile: &str,
+        secret: &str,
+        mut call: F,
+    ) -> Result<SessionCredentials>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<SessionCredentials>>,
+    {
+        let token_code = MfaManager::generate_totp_code(secret)?;
+
+        match call(token_code).await {
+            Err(e) if e.aws_kind() == Some(AwsErrorKind::InvalidMfaCode) => {
+                let remaining = MfaManager::get_time_remaining(secret)?;
+                log::info!(
+                    "MFA code for '{}' was rejected, retrying in {}s",
+                    profile,
+                    remaining
+                );
+                cancellation::sleep(Duration::from_secs(remaining + 1)).await?;
+
+                call(MfaManager::generate_totp_code(secret)?).await
+            }
+            result => result,
+        }
+    }
+
+    /// Drop cached credentials AWS reported as expired so the next call mints new ones.
+    pub fn handle_error(profile: &str, error: &AppError) {
+        if error.aws_kind() == Some(AwsErrorKind::ExpiredToken) {
+            log::info!("Credentials of '{}' expired, clearing the cache", profile);
+            let _ = KeyringManager::delete_session_credentials(profile);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::fake_backend::{use_offline_environment, FakeBackend, Operation, SEED};
+    use time::Duration;
+
+    const EXPIRED: &str = "An error occurred (ExpiredToken) when calling the GetSessionToken \
+                           operation: The security token included in the request is expired";
+
+    fn credentials(token: &str) -> SessionCredentials {
+        SessionCredentials {
+            access_key_id: "ASIAQUEUED".to_string(),
+            secret_access_key: "queued-secret".to_string(),
+            session_token: token.to_string(),
+            expiration: OffsetDateTime::now_utc() + Duration::hours(1),
+        }
+    }
+
+    fn is_totp_code(code: &str) -> bool {
+        code.len() == 6 && code.chars().all(|c| c.is_ascii_digit())
+    }
+
+    #[tokio::test]
+    async fn mints_then_reuses_session_credentials() {
+        use_offline_environment();
+        KeyringManager::store_secret("dev", SEED).unwrap();
+        let backend = FakeBackend::new();
+
+        let (first, cached) = CredentialManager::resolve(&backend, "dev").await.unwrap();
+        assert!(!cached);
+        let (second, cached) = CredentialManager::resolve(&backend, "dev").await.unwrap();
+        assert!(cached);
+        assert_eq!(first.session_token, second.session_token);
+
+        // The serial comes from the config, so IAM is not asked for it
+        let calls = backend.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].operation, Operation::GetSessionToken);
+        assert_eq!(calls[0].profile.as_deref(), Some("dev"));
+        assert_eq!(calls[0].args[0], "arn:aws:iam::111111111111:mfa/alice");
+        assert!(is_totp_code(&calls[0].args[1]));
+    }
+
+    #[tokio::test]
+    async fn looks_up_the_device_of_the_user() {
+        use_offline_environment();
+        KeyringManager::store_secret("ops", SEED).unwrap();
+        let backend = FakeBackend::new()
+            .with_identity("ops", "111111111111", "bob")
+            .with_mfa_device("bob", "arn:aws:iam::111111111111:mfa/bob")
+            .with_session_token(credentials("queued"));
+
+        let credentials = CredentialManager::refresh(&backend, "ops").await.unwrap();
+
+        assert_eq!(credentials.session_token, "queued");
+        assert_eq!(backend.calls_to(Operation::GetCallerIdentity), 1);
+        assert_eq!(backend.calls_to(Operation::ListMfaDevices), 1);
+        let call = backend.calls().pop().unwrap();
+        assert_eq!(call.operation, Operation::GetSessionToken);
+        assert_eq!(call.args[0], "arn:aws:iam::111111111111:mfa/bob");
+    }
+
+    #[tokio::test]
+    async fn assumes_roles_with_the_device_of_the_source_profile() {
+        use_offline_environment();
+        KeyringManager::store_secret("ops", SEED).unwrap();
+        let backend = FakeBackend::new()
+            .with_identity("ops", "111111111111", "bob")
+            .with_mfa_device("bob", "arn:aws:iam::111111111111:mfa/bob");
+
+        let (_, cached) = CredentialManager::resolve(&backend, "admin").await.unwrap();
+
+        assert!(!cached);
+        let call = backend.calls().pop().unwrap();
+        assert_eq!(call.operation, Operation::AssumeRole);
+        assert_eq!(call.profile.as_deref(), Some("ops"));
+        assert_eq!(
+            call.args[..3],
+            [
+                "arn:aws:iam::222222222222:role/admin",
+                "tests",
+                "arn:aws:iam::111111111111:mfa/bob"
+            ]
+        );
+        assert!(is_totp_code(&call.args[3]));
+        assert!(KeyringManager::get_session_credentials("admin").is_ok());
+    }
+
+    #[tokio::test]
+    async fn needs_a_secret_before_calling_aws() {
+        use_offline_environment();
+        let backend = FakeBackend::new();
+
+        assert!(CredentialManager::resolve(&backend, "locked")
+            .await
+            .is_err());
+        assert_eq!(backend.calls_to(Operation::GetSessionToken), 0);
+    }
+
+    #[tokio::test]
+    async fn forgets_credentials_reported_as_expired() {
+        use_offline_environment();
+        KeyringManager::store_secret("stale", SEED).unwrap();
+        KeyringManager::store_session_credentials("stale", &credentials("old")).unwrap();
+        let backend = FakeBackend::new().fail_next(Operation::GetSessionToken, EXPIRED);
+
+        let error = CredentialManager::refresh(&backend, "stale")
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.aws_kind(), Some(AwsErrorKind::ExpiredToken));
+        assert!(KeyringManager::get_session_credentials("stale").is_err());
+
+        let (_, cached) = CredentialManager::resolve(&backend, "stale").await.unwrap();
+        assert!(!cached);
+        assert_eq!(backend.calls_to(Operation::GetSessionToken), 2);
+    }
+}
@@ -0,0 +1,99 @@
+use super::ini_document::IniDocument;
+use super::models::SessionCredentials;
+use super::settings::AwsSettings;
+use super::utils::get_aws_credentials_path;
+use crate::error::{AppError, Result};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Comment marking a section as written by akaw. Sections without it are never touched.
+const MARKER: &str = "# Managed by akaw, expires ";
+
+/// Session credentials published as static keys in `~/.aws/credentials`, for tools that
+/// cannot use `credential_process`.
+pub struct DerivedProfile;
+
+impl DerivedProfile {
+    /// Name of the section holding the session credentials of a profile.
+    pub fn name(profile: &str) -> String {
+        format!("{}{}", profile, AwsSettings::load().derived_profile_suffix)
+    }
+
+    /// Write the credentials into the derived section, replacing an earlier set.
+    pub fn write(profile: &str, credentials: &SessionCredentials) -> Result<String> {
+        let name = Self::name(profile);
+        let path = get_aws_credentials_path()?;
+        let mut document = IniDocument::load(&path)?;
+
+        if document.has_section(&name) && Self::expiration(&document, &name).is_none() {
+            return Err(AppError::Custom(format!(
+                "Profile '{}' already exists in the credentials file and is not managed by akaw",
+                name
+            )));
+        }
+
+        let expiration = credentials
+            .expiration
+            .format(&Rfc3339)
+            .map_err(|e| AppError::Custom(e.to_string()))?;
+        let body = vec![
+            format!("{}{}", MARKER, expiration),
+            format!("aws_access_key_id = {}", credentials.access_key_id),
+            format!("aws_secret_access_key = {}", credentials.secret_access_key),
+            format!("aws_session_token = {}", credentials.session_token),
+        ];
+
+        document.replace_section(&name, body);
+        document.save(&path)?;
+
+        Ok(name)
+    }
+
+    /// Remove the derived section of a profile, if akaw wrote it.
+    pub fn remove(profile: &str) -> Result<()> {
+        let name = Self::name(profile);
+        Self::remove_sections(|section, _| section == name)
+    }
+
+    /// Remove every derived section whose credentials have expired.
+    pub fn prune_expired() -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+        Self::remove_sections(|_, expiration| expiration <= now)
+    }
+
+    fn remove_sections(should_remove: impl Fn(&str, OffsetDateTime) -> bool) -> Result<()> {
+        let path = get_aws_credentials_path()?;
+        if !path.exists() {
+            return Ok(());
+        }
+        let mut document = IniDocument::load(&path)?;
+
+        let removed: Vec<String> = document
+            .sections()
+            .into_iter()
+            .filter(|section| {
+                Self::expiration(&document, section)
+                    .is_some_and(|expiration| should_remove(section, expiration))
+            })
+            .map(|section| section.to_string())
+            .collect();
+
+        if !removed.is_empty() {
+            for section in &removed {
+                document.remove_section(section);
+            }
+            document.save(&path)?;
+            log::info!("Removed derived credentials: {}", removed.join(", "));
+        }
+
+        Ok(())
+    }
+
+    fn expiration(document: &IniDocument, section: &str) -> Option<OffsetDateTime> {
+        document
+            .comments(section)
+            .iter()
+            .find_map(|line| line.trim().strip_prefix(MARKER))
+            .and_then(|value| OffsetDateTime::parse(value.trim(), &Rfc3339).ok())
+    }
+}
@@ -0,0 +1,420 @@
+use super::backend::AwsBackend;
+use super::models::{
+    AssumeRoleRequest, CallerIdentity, MfaDevice, SessionCredentials, VirtualMfaDevice,
+};
+use super::utils::{AKAW_SESSIONS_FILE_ENV, AWS_CONFIG_FILE_ENV, AWS_SHARED_CREDENTIALS_FILE_ENV};
+use crate::error::{AppError, Result};
+use crate::util::dirs::PORTABLE_ROOT_ENV;
+use async_trait::async_trait;
+use keyring::credential::{Credential, CredentialApi, CredentialBuilderApi};
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use time::{Duration, OffsetDateTime};
+
+/// Secret of every virtual MFA device the fake creates.
+pub const SEED: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+/// Profiles of the shared config the flow tests run against.
+const CONFIG: &str = "\
+[default]
+region = eu-west-1
+
+[profile dev]
+mfa_serial = arn:aws:iam::111111111111:mfa/alice
+
+[profile ops]
+region = eu-west-1
+
+[profile admin]
+role_arn = arn:aws:iam::222222222222:role/admin
+source_profile = ops
+role_session_name = tests
+
+[profile stale]
+mfa_serial = arn:aws:iam::111111111111:mfa/stale
+
+[profile fresh]
+region = eu-west-1
+
+[profile blocked]
+region = eu-west-1
+
+[profile locked]
+mfa_serial = arn:aws:iam::111111111111:mfa/locked
+
+[profile brief]
+mfa_serial = arn:aws:iam::111111111111:mfa/brief
+";
+
+const CREDENTIALS: &str = "\
+[dev]
+aws_access_key_id = AKIADEV
+aws_secret_access_key = dev-secret
+
+[ops]
+aws_access_key_id = AKIAOPS
+aws_secret_access_key = ops-secret
+";
+
+/// Point the home directory of akaw, and with it the shared AWS files, at a fixture directory, and keep keyring
+/// entries in memory, so flows run without touching the user's setup. The environment is
+/// shared by all tests, so each test uses profiles of its own.
+pub fn use_offline_environment() {
+    static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+    ROOT.get_or_init(|| {
+        let root = std::env::temp_dir().join(format!("akaw-tests-{}", std::process::id()));
+        let aws = root.join(".aws");
+        fs::create_dir_all(&aws).unwrap();
+        fs::write(aws.join("config"), CONFIG).unwrap();
+        fs::write(aws.join("credentials"), CREDENTIALS).unwrap();
+
+        // Overrides in the developer's environment would win over the portable root
+        std::env::set_var(PORTABLE_ROOT_ENV, &root);
+        std::env::set_var(AWS_CONFIG_FILE_ENV, aws.join("config"));
+        std::env::set_var(AWS_SHARED_CREDENTIALS_FILE_ENV, aws.join("credentials"));
+        std::env::remove_var(AKAW_SESSIONS_FILE_ENV);
+        keyring::set_default_credential_builder(Box::new(MemoryKeyring));
+
+        root
+    });
+}
+
+/// Keyring whose entries live in memory for the lifetime of the process. Unlike the mock of
+/// the keyring crate, entries built separately for the same key share their secret.
+struct MemoryKeyring;
+
+struct MemoryCredential {
+    key: (String, String),
+}
+
+fn memory_store() -> &'static Mutex<HashMap<(String, String), Vec<u8>>> {
+    static STORE: OnceLock<Mutex<HashMap<(String, String), Vec<u8>>>> = OnceLock::new();
+    STORE.get_or_init(Default::default)
+}
+
+impl CredentialBuilderApi for MemoryKeyring {
+    fn build(
+        &self,
+        _target: Option<&str>,
+        service: &str,
+        user: &str,
+    ) -> keyring::Result<Box<Credential>> {
+        Ok(Box::new(MemoryCredential {
+            key: (service.to_string(), user.to_string()),
+        }))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl CredentialApi for MemoryCredential {
+    fn set_secret(&self, secret: &[u8]) -> keyring::Result<()> {
+        memory_store()
+            .lock()
+            .unwrap()
+            .insert(self.key.clone(), secret.to_vec());
+        Ok(())
+    }
+
+    fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+        memory_store()
+            .lock()
+            .unwrap()
+            .get(&self.key)
+            .cloned()
+            .ok_or(keyring::Error::NoEntry)
+    }
+
+    fn delete_credential(&self) -> keyring::Result<()> {
+        memory_store()
+            .lock()
+            .unwrap()
+            .remove(&self.key)
+            .map(|_| ())
+            .ok_or(keyring::Error::NoEntry)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Operations of the fake, used to script failures and inspect recorded calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    CreateVirtualMfaDevice,
+    EnableMfaDevice,
+    GetSessionToken,
+    AssumeRole,
+    GetRoleCredentials,
+    ListMfaDevices,
+    GetCallerIdentity,
+}
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub operation: Operation,
+    pub profile: Option<String>,
+    pub args: Vec<String>,
+}
+
+#[derive(Default)]
+struct State {
+    identities: HashMap<String, CallerIdentity>,
+    mfa_devices: HashMap<String, Vec<MfaDevice>>,
+    session_tokens: VecDeque<SessionCredentials>,
+    failures: HashMap<Operation, VecDeque<String>>,
+    calls: Vec<Call>,
+}
+
+/// Scriptable in-memory backend. Responses are configured up front and every call is
+/// recorded, so flows like `connect` or `setup_mfa_device` can run without the CLI.
+#[derive(Default)]
+pub struct FakeBackend {
+    state: Mutex<State>,
+}
+
+impl FakeBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `get-caller-identity` for a profile with the given user.
+    pub fn with_identity(self, profile: &str, account: &str, username: &str) -> Self {
+        self.state.lock().unwrap().identities.insert(
+            profile.to_string(),
+            CallerIdentity {
+                user_id: format!("AIDA{}", username.to_uppercase()),
+                account: account.to_string(),
+                arn: format!("arn:aws:iam::{}:user/{}", account, username),
+            },
+        );
+        self
+    }
+
+    /// Register an MFA device for a user.
+    pub fn with_mfa_device(self, username: &str, serial_number: &str) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .mfa_devices
+            .entry(username.to_string())
+            .or_default()
+            .push(MfaDevice {
+                user_name: username.to_string(),
+                serial_number: serial_number.to_string(),
+                enable_date: OffsetDateTime::now_utc(),
+            });
+        self
+    }
+
+    /// Queue credentials returned by the next `get-session-token` or `assume-role` call.
+    /// Without queued credentials a fresh set valid for 12 hours is generated.
+    pub fn with_session_token(self, credentials: SessionCredentials) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .session_tokens
+            .push_back(credentials);
+        self
+    }
+
+    /// Make the next call of an operation fail with the given CLI error output.
+    pub fn fail_next(self, operation: Operation, message: &str) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .entry(operation)
+            .or_default()
+            .push_back(message.to_string());
+        self
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    pub fn calls_to(&self, operation: Operation) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .calls
+            .iter()
+            .filter(|call| call.operation == operation)
+            .count()
+    }
+
+    fn next_credentials(&self) -> SessionCredentials {
+        let queued = self.state.lock().unwrap().session_tokens.pop_front();
+
+        queued.unwrap_or_else(|| SessionCredentials {
+            access_key_id: "ASIAFAKEACCESSKEY".to_string(),
+            secret_access_key: "fake-secret-access-key".to_string(),
+            session_token: "fake-session-token".to_string(),
+            expiration: OffsetDateTime::now_utc() + Duration::hours(12),
+        })
+    }
+
+    fn record(&self, operation: Operation, profile: Option<&str>, args: &[&str]) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        state.calls.push(Call {
+            operation,
+            profile: profile.map(|p| p.to_string()),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        });
+
+        match state
+            .failures
+            .get_mut(&operation)
+            .and_then(|f| f.pop_front())
+        {
+            Some(message) => Err(AppError::AwsCli(message)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl AwsBackend for FakeBackend {
+    async fn create_virtual_mfa_device(
+        &self,
+        username: &str,
+        outfile: &str,
+        profile: Option<&str>,
+    ) -> Result<VirtualMfaDevice> {
+        self.record(
+            Operation::CreateVirtualMfaDevice,
+            profile,
+            &[username, outfile],
+        )?;
+
+        let account = {
+            let state = self.state.lock().unwrap();
+            profile
+                .and_then(|p| state.identities.get(p))
+                .map(|identity| identity.account.clone())
+                .unwrap_or_else(|| "000000000000".to_string())
+        };
+
+        Ok(VirtualMfaDevice {
+            serial_number: format!("arn:aws:iam::{}:mfa/{}", account, username),
+            base32_string_seed: Some(SEED.to_string()),
+        })
+    }
+
+    async fn enable_mfa_device(
+        &self,
+        username: &str,
+        serial_number: &str,
+        code1: &str,
+        code2: &str,
+        profile: Option<&str>,
+    ) -> Result<()> {
+        self.record(
+            Operation::EnableMfaDevice,
+            profile,
+            &[username, serial_number, code1, code2],
+        )?;
+
+        self.state
+            .lock()
+            .unwrap()
+            .mfa_devices
+            .entry(username.to_string())
+            .or_default()
+            .push(MfaDevice {
+                user_name: username.to_string(),
+                serial_number: serial_number.to_string(),
+                enable_date: OffsetDateTime::now_utc(),
+            });
+
+        Ok(())
+    }
+
+    async fn get_session_token(
+        &self,
+        serial_number: &str,
+        token_code: &str,
+        profile: Option<&str>,
+    ) -> Result<SessionCredentials> {
+        self.record(
+            Operation::GetSessionToken,
+            profile,
+            &[serial_number, token_code],
+        )?;
+
+        Ok(self.next_credentials())
+    }
+
+    async fn assume_role(
+        &self,
+        request: &AssumeRoleRequest,
+        profile: Option<&str>,
+    ) -> Result<SessionCredentials> {
+        let mut args = vec![
+            request.role_arn.as_str(),
+            request.role_session_name.as_str(),
+        ];
+        args.extend(request.serial_number.as_deref());
+        args.extend(request.token_code.as_deref());
+        self.record(Operation::AssumeRole, profile, &args)?;
+
+        Ok(self.next_credentials())
+    }
+
+    async fn get_role_credentials(
+        &self,
+        account_id: &str,
+        role_name: &str,
+        access_token: &str,
+        region: &str,
+    ) -> Result<SessionCredentials> {
+        self.record(
+            Operation::GetRoleCredentials,
+            None,
+            &[account_id, role_name, access_token, region],
+        )?;
+
+        Ok(self.next_credentials())
+    }
+
+    async fn list_mfa_devices(
+        &self,
+        username: &str,
+        profile: Option<&str>,
+    ) -> Result<Vec<MfaDevice>> {
+        self.record(Operation::ListMfaDevices, profile, &[username])?;
+
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .mfa_devices
+            .get(username)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_caller_identity(&self, profile: Option<&str>) -> Result<CallerIdentity> {
+        self.record(Operation::GetCallerIdentity, profile, &[])?;
+
+        let state = self.state.lock().unwrap();
+        profile
+            .and_then(|p| state.identities.get(p))
+            .cloned()
+            .ok_or_else(|| {
+                AppError::AwsCli(
+                    "Unable to locate credentials. You can configure credentials by running \"aws configure\"."
+                        .to_string(),
+                )
+            })
+    }
+}
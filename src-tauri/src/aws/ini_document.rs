@@ -0,0 +1,408 @@
+use crate::error::Result;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// What a line of the file holds. Comments, blank lines and anything else unrecognized
+/// are kept verbatim.
+#[derive(Debug, Clone, PartialEq)]
+enum LineKind {
+    Section(String),
+    Entry(String),
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    raw: String,
+    kind: LineKind,
+}
+
+impl Line {
+    fn parse(raw: &str) -> Self {
+        let trimmed = raw.trim();
+
+        let kind = if trimmed.starts_with('#') || trimmed.starts_with(';') {
+            LineKind::Other
+        } else if let Some(name) = trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            LineKind::Section(name.trim().to_string())
+        } else if let Some(pos) = separator(trimmed) {
+            LineKind::Entry(trimmed[..pos].trim().to_string())
+        } else {
+            LineKind::Other
+        };
+
+        Self {
+            raw: raw.to_string(),
+            kind,
+        }
+    }
+
+    fn entry(key: &str, value: &str) -> Self {
+        if value.is_empty() {
+            Self::parse(&format!("{} =", key))
+        } else {
+            Self::parse(&format!("{} = {}", key, value))
+        }
+    }
+
+    fn is_section(&self, name: &str) -> bool {
+        matches!(&self.kind, LineKind::Section(section) if section == name)
+    }
+
+    /// Whether the line sets `key` of its section. Indented lines are the settings nested
+    /// under a key such as `s3 =`, not keys of the section.
+    fn is_entry(&self, key: &str) -> bool {
+        matches!(&self.kind, LineKind::Entry(k) if k == key) && !self.raw.starts_with([' ', '\t'])
+    }
+
+    fn is_comment(&self) -> bool {
+        let trimmed = self.raw.trim_start();
+        trimmed.starts_with('#') || trimmed.starts_with(';')
+    }
+
+    fn is_blank(&self) -> bool {
+        self.raw.trim().is_empty()
+    }
+
+    fn value(&self) -> &str {
+        separator(&self.raw).map_or("", |pos| self.raw[pos + 1..].trim())
+    }
+
+    /// Replace the value, keeping the key and its spacing as written.
+    fn set_value(&mut self, value: &str) {
+        if let Some(pos) = separator(&self.raw) {
+            let rest = &self.raw[pos + 1..];
+            let spacing = &rest[..rest.len() - rest.trim_start().len()];
+            self.raw = format!("{}{}{}", &self.raw[..=pos], spacing, value);
+        }
+    }
+}
+
+fn separator(text: &str) -> Option<usize> {
+    text.find(['=', ':'])
+}
+
+/// A `key = value` line with its 1-based line number, for error messages.
+#[derive(Debug, Clone)]
+pub struct IniEntry {
+    pub line: usize,
+    pub key: String,
+    pub value: String,
+}
+
+/// Lossless model of an ini file such as `~/.aws/config`, `credentials` or `sessions`.
+/// Edits only touch the affected lines, so comments, blank lines, key order and formatting
+/// everywhere else survive a load and save.
+#[derive(Debug, Clone)]
+pub struct IniDocument {
+    lines: Vec<Line>,
+    line_ending: &'static str,
+    trailing_newline: bool,
+}
+
+impl IniDocument {
+    pub fn parse(content: &str) -> Self {
+        Self {
+            lines: content.lines().map(Line::parse).collect(),
+            line_ending: if content.contains("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            },
+            trailing_newline: content.is_empty() || content.ends_with('\n'),
+        }
+    }
+
+    /// Read a file, or start an empty document when it does not exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::parse("")),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Names of all sections, in file order.
+    pub fn sections(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match &line.kind {
+                LineKind::Section(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn has_section(&self, section: &str) -> bool {
+        self.section_range(section).is_some()
+    }
+
+    /// The header line number and the entries of every section, in file order.
+    pub fn entries(&self) -> Vec<(String, usize, Vec<IniEntry>)> {
+        let mut sections: Vec<(String, usize, Vec<IniEntry>)> = Vec::new();
+
+        for (i, line) in self.lines.iter().enumerate() {
+            match &line.kind {
+                LineKind::Section(name) => sections.push((name.clone(), i + 1, Vec::new())),
+                LineKind::Entry(key) => {
+                    if let Some((_, _, entries)) = sections.last_mut() {
+                        entries.push(IniEntry {
+                            line: i + 1,
+                            key: key.clone(),
+                            value: line.value().to_string(),
+                        });
+                    }
+                }
+                LineKind::Other => {}
+            }
+        }
+
+        sections
+    }
+
+    /// The comments of a section, from the block above its header and from its body.
+    pub fn comments(&self, section: &str) -> Vec<&str> {
+        let Some((start, _, end)) = self.section_range(section) else {
+            return Vec::new();
+        };
+
+        self.lines[start..end]
+            .iter()
+            .filter(|line| line.is_comment())
+            .map(|line| line.raw.as_str())
+            .collect()
+    }
+
+    /// Set `key = value` in `section`. An existing key keeps its place, a new key goes after
+    /// the last line of the section, and a missing section is appended to the file.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let Some((_, header, end)) = self.section_range(section) else {
+            self.append_section(section, vec![Line::entry(key, value)]);
+            return;
+        };
+
+        match (header + 1..end).find(|&i| self.lines[i].is_entry(key)) {
+            Some(i) => self.lines[i].set_value(value),
+            None => {
+                let at = self.body_end(header, end);
+                self.lines.insert(at, Line::entry(key, value));
+            }
+        }
+    }
+
+    /// Replace the body of a section with raw lines, appending the section when missing.
+    /// The comments above its header are kept.
+    pub fn replace_section(&mut self, section: &str, body: Vec<String>) {
+        let body: Vec<Line> = body.iter().map(|raw| Line::parse(raw)).collect();
+
+        match self.section_range(section) {
+            Some((_, header, end)) => {
+                let body_end = self.body_end(header, end);
+                self.lines.splice(header + 1..body_end, body);
+            }
+            None => self.append_section(section, body),
+        }
+    }
+
+    /// Remove a section along with the comments directly above its header.
+    /// Returns whether it existed.
+    pub fn remove_section(&mut self, section: &str) -> bool {
+        let Some((start, _, end)) = self.section_range(section) else {
+            return false;
+        };
+
+        self.lines.drain(start..end);
+        // Drop the blank line that separated the removed section from the previous one
+        if start > 0 && start == self.lines.len() && self.lines[start - 1].is_blank() {
+            self.lines.pop();
+        }
+
+        true
+    }
+
+    /// Start of the comment block attached to a section, its header, and the first line
+    /// after it, which is where the next section's attached comments begin.
+    fn section_range(&self, section: &str) -> Option<(usize, usize, usize)> {
+        let header = self
+            .lines
+            .iter()
+            .position(|line| line.is_section(section))?;
+        let start = self.attached_comments_start(header);
+
+        let end = self.lines[header + 1..]
+            .iter()
+            .position(|line| matches!(line.kind, LineKind::Section(_)))
+            .map(|i| self.attached_comments_start(header + 1 + i))
+            .unwrap_or(self.lines.len());
+
+        Some((start, header, end.max(header + 1)))
+    }
+
+    /// Comments directly above a header, without a blank line in between, describe it.
+    fn attached_comments_start(&self, header: usize) -> usize {
+        let mut start = header;
+        while start > 0 && self.lines[start - 1].is_comment() {
+            start -= 1;
+        }
+        start
+    }
+
+    /// Position after the last non-blank line of a section, keeping the blank lines that
+    /// separate it from the next section.
+    fn body_end(&self, header: usize, end: usize) -> usize {
+        (header + 1..end)
+            .rev()
+            .find(|&i| !self.lines[i].is_blank())
+            .map(|i| i + 1)
+            .unwrap_or(header + 1)
+    }
+
+    fn append_section(&mut self, section: &str, body: Vec<Line>) {
+        if self.lines.last().is_some_and(|line| !line.is_blank()) {
+            self.lines.push(Line::parse(""));
+        }
+        self.lines.push(Line::parse(&format!("[{}]", section)));
+        self.lines.extend(body);
+    }
+}
+
+impl fmt::Display for IniDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                f.write_str(self.line_ending)?;
+            }
+            f.write_str(&line.raw)?;
+        }
+
+        if self.trailing_newline && !self.lines.is_empty() {
+            f.write_str(self.line_ending)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# Shared settings
+[default]
+region=eu-west-1
+output   =  json
+
+; Work account
+[profile dev]
+mfa_serial = arn:aws:iam::111111111111:mfa/alice
+s3 =
+  max_concurrent_requests = 20
+region = eu-west-1
+
+[services local]
+s3 =
+  endpoint_url = http://localhost:4566
+";
+
+    #[test]
+    fn keeps_the_file_as_written() {
+        assert_eq!(IniDocument::parse(CONFIG).to_string(), CONFIG);
+
+        let unterminated = "[default]\n\n\nregion = eu-west-1";
+        assert_eq!(IniDocument::parse(unterminated).to_string(), unterminated);
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let content = CONFIG.replace('\n', "\r\n");
+        let mut document = IniDocument::parse(&content);
+
+        assert_eq!(document.to_string(), content);
+
+        document.set("default", "output", "text");
+        document.set("profile ops", "region", "us-east-1");
+        let updated = document.to_string();
+        assert!(updated.contains("output   =  text\r\n"));
+        assert!(updated.ends_with("\r\n\r\n[profile ops]\r\nregion = us-east-1\r\n"));
+        assert!(!updated.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn sets_keys_in_place() {
+        let mut document = IniDocument::parse(CONFIG);
+
+        document.set("default", "region", "us-east-1");
+        document.set("default", "cli_pager", "");
+        document.set("profile dev", "duration_seconds", "3600");
+
+        let expected = CONFIG
+            .replace("region=eu-west-1\n", "region=us-east-1\n")
+            .replace("json\n\n", "json\ncli_pager =\n\n")
+            .replace(
+                "region = eu-west-1\n\n[services",
+                "region = eu-west-1\nduration_seconds = 3600\n\n[services",
+            );
+        assert_eq!(document.to_string(), expected);
+    }
+
+    #[test]
+    fn leaves_nested_settings_alone() {
+        let mut document = IniDocument::parse(CONFIG);
+
+        document.set("services local", "endpoint_url", "http://localhost:9000");
+        document.set("profile dev", "max_concurrent_requests", "5");
+
+        let updated = document.to_string();
+        assert!(updated.contains("  endpoint_url = http://localhost:4566\n"));
+        assert!(updated.ends_with("endpoint_url = http://localhost:9000\n"));
+        assert!(updated.contains("  max_concurrent_requests = 20\n"));
+        assert!(updated.contains("region = eu-west-1\nmax_concurrent_requests = 5\n"));
+    }
+
+    #[test]
+    fn replaces_a_section_body() {
+        let mut document = IniDocument::parse(CONFIG);
+
+        document.replace_section(
+            "profile dev",
+            vec!["region = us-east-1".to_string(), "# rotated".to_string()],
+        );
+        document.replace_section("profile ops", vec!["region = eu-north-1".to_string()]);
+
+        let expected = format!(
+            "{}[profile dev]\nregion = us-east-1\n# rotated\n\n[services{}\n[profile ops]\nregion = eu-north-1\n",
+            &CONFIG[..CONFIG.find("[profile dev]").unwrap()],
+            &CONFIG[CONFIG.find("[services").unwrap() + "[services".len()..],
+        );
+        assert_eq!(document.to_string(), expected);
+        assert_eq!(
+            document.comments("profile dev"),
+            ["; Work account", "# rotated"]
+        );
+    }
+
+    #[test]
+    fn removes_a_section_with_its_comments() {
+        let mut document = IniDocument::parse(CONFIG);
+
+        assert!(document.remove_section("profile dev"));
+        assert!(!document.remove_section("profile dev"));
+        assert!(document.remove_section("services local"));
+
+        assert_eq!(
+            document.to_string(),
+            "# Shared settings\n[default]\nregion=eu-west-1\noutput   =  json\n"
+        );
+        assert_eq!(document.sections(), ["default"]);
+    }
+}
@@ -1,4 +1,8 @@
-use super::aws_cli::AwsCli;
+use super::aws_config::AwsConfig;
+use super::backend::AwsBackend;
+use super::cancellation;
+use super::keyring_manager::KeyringManager;
+use super::models::VirtualMfaDevice;
 use crate::error::{AppError, Result};
 use image::DynamicImage;
 use rqrr::PreparedImage;
@@ -10,14 +14,46 @@ use url::Url;
 pub struct MfaManager;
 
 impl MfaManager {
-    pub async fn setup_mfa_device(username: &str, profile: &str) -> Result<(String, String)> {
+    /// Give the user of a profile an MFA device and store its secret, either from a new
+    /// virtual device or from the QR code image of an existing one. Returns the device serial
+    /// and whether it was imported.
+    pub async fn register(
+        backend: &dyn AwsBackend,
+        profile: &str,
+        import_qr: Option<&str>,
+    ) -> Result<(String, bool)> {
+        let username = AwsConfig::get_username(backend, profile).await?;
+
+        if let Some(qr_path) = import_qr {
+            let secret = Self::import_qr_code(qr_path)?;
+            let serial = Self::fetch_mfa_serial(backend, &username, profile).await?;
+            KeyringManager::store_secret(profile, &secret)?;
+            Ok((serial, true))
+        } else {
+            let (serial, secret) = Self::setup_mfa_device(backend, &username, profile).await?;
+            KeyringManager::store_secret(profile, &secret)?;
+            Ok((serial, false))
+        }
+    }
+
+    pub async fn setup_mfa_device(
+        backend: &dyn AwsBackend,
+        username: &str,
+        profile: &str,
+    ) -> Result<(String, String)> {
         let temp_file = format!("qr_temp_{}.png", profile);
 
-        let serial_number = Self::create_virtual_mfa(username, profile, &temp_file).await?;
-        let secret = Self::extract_secret_from_qr(&temp_file)?;
+        let device = Self::create_virtual_mfa(backend, username, profile, &temp_file).await?;
+        let serial_number = device.serial_number;
+        let secret = match device.base32_string_seed {
+            Some(seed) => seed,
+            None => Self::extract_secret_from_qr(&temp_file)?,
+        };
         let (code1, code2) = Self::generate_consecutive_codes(&secret).await?;
-        AwsCli::enable_mfa_device(username, &serial_number, &code1, &code2, Some(profile)).await?;
-        Self::verify_mfa_device(username, profile, &serial_number).await?;
+        backend
+            .enable_mfa_device(username, &serial_number, &code1, &code2, Some(profile))
+            .await?;
+        Self::verify_mfa_device(backend, username, profile, &serial_number).await?;
 
         let _ = std::fs::remove_file(&temp_file);
 
@@ -32,8 +68,23 @@ impl MfaManager {
         Self::parse_secret_from_uri(&qr_data)
     }
 
-    pub async fn fetch_mfa_serial(username: &str, profile: &str) -> Result<String> {
-        let devices = AwsCli::list_mfa_devices(username, Some(profile)).await?;
+    /// The MFA device of a profile: its `mfa_serial` from the config when set, which saves the
+    /// IAM round-trips, otherwise the first device registered to the profile's user.
+    pub async fn resolve_mfa_serial(backend: &dyn AwsBackend, profile: &str) -> Result<String> {
+        if let Some(serial) = AwsConfig::get_profile(profile)?.mfa_serial {
+            return Ok(serial);
+        }
+
+        let username = AwsConfig::get_username(backend, profile).await?;
+        Self::fetch_mfa_serial(backend, &username, profile).await
+    }
+
+    pub async fn fetch_mfa_serial(
+        backend: &dyn AwsBackend,
+        username: &str,
+        profile: &str,
+    ) -> Result<String> {
+        let devices = backend.list_mfa_devices(username, Some(profile)).await?;
 
         devices
             .first()
@@ -68,7 +119,7 @@ impl MfaManager {
             .generate_current()
             .map_err(|e| AppError::Totp(e.to_string()))?;
 
-        tokio::time::sleep(Duration::from_secs(30)).await;
+        cancellation::sleep(Duration::from_secs(30)).await?;
 
         let code2 = totp
             .generate_current()
@@ -113,13 +164,24 @@ impl MfaManager {
             .ok_or_else(|| AppError::QrCode("Secret not found in QR code".to_string()))
     }
 
-    async fn create_virtual_mfa(username: &str, profile: &str, outfile: &str) -> Result<String> {
-        let device = AwsCli::create_virtual_mfa_device(username, outfile, Some(profile)).await?;
-        Ok(device.serial_number)
+    async fn create_virtual_mfa(
+        backend: &dyn AwsBackend,
+        username: &str,
+        profile: &str,
+        outfile: &str,
+    ) -> Result<VirtualMfaDevice> {
+        backend
+            .create_virtual_mfa_device(username, outfile, Some(profile))
+            .await
     }
 
-    async fn verify_mfa_device(username: &str, profile: &str, serial: &str) -> Result<()> {
-        let devices = AwsCli::list_mfa_devices(username, Some(profile)).await?;
+    async fn verify_mfa_device(
+        backend: &dyn AwsBackend,
+        username: &str,
+        profile: &str,
+        serial: &str,
+    ) -> Result<()> {
+        let devices = backend.list_mfa_devices(username, Some(profile)).await?;
 
         let found = devices.iter().any(|d| d.serial_number == serial);
 
@@ -130,3 +192,70 @@ impl MfaManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::credentials::CredentialManager;
+    use crate::aws::fake_backend::{use_offline_environment, FakeBackend, Operation, SEED};
+
+    // Enabling a device waits for the next code, so these run on a paused clock.
+
+    #[tokio::test(start_paused = true)]
+    async fn registers_a_device_that_connects_afterwards() {
+        use_offline_environment();
+        let backend = FakeBackend::new().with_identity("fresh", "333333333333", "carol");
+
+        let (serial, imported) = MfaManager::register(&backend, "fresh", None).await.unwrap();
+
+        assert_eq!(serial, "arn:aws:iam::333333333333:mfa/carol");
+        assert!(!imported);
+        assert_eq!(KeyringManager::get_secret("fresh").unwrap(), SEED);
+        let enable = backend
+            .calls()
+            .into_iter()
+            .find(|call| call.operation == Operation::EnableMfaDevice)
+            .unwrap();
+        assert_eq!(enable.args[..2], ["carol", serial.as_str()]);
+        assert_eq!(backend.calls_to(Operation::ListMfaDevices), 1);
+
+        let (_, cached) = CredentialManager::resolve(&backend, "fresh").await.unwrap();
+        assert!(!cached);
+        let call = backend.calls().pop().unwrap();
+        assert_eq!(call.operation, Operation::GetSessionToken);
+        assert_eq!(call.args[0], serial);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_no_secret_when_enabling_fails() {
+        use_offline_environment();
+        let backend = FakeBackend::new()
+            .with_identity("blocked", "333333333333", "dave")
+            .fail_next(
+                Operation::EnableMfaDevice,
+                "An error occurred (EntityAlreadyExists) when calling the EnableMFADevice \
+                 operation: MFA device already exists",
+            );
+
+        let error = MfaManager::register(&backend, "blocked", None)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("EntityAlreadyExists"));
+        assert!(!KeyringManager::has_secret("blocked"));
+        assert_eq!(backend.calls_to(Operation::CreateVirtualMfaDevice), 1);
+        assert_eq!(backend.calls_to(Operation::ListMfaDevices), 0);
+    }
+
+    #[test]
+    fn reads_the_secret_of_an_otpauth_uri() {
+        let uri = "otpauth://totp/Amazon%20Web%20Services:carol@333333333333\
+                   ?secret=ABCDEF234567&issuer=Amazon%20Web%20Services";
+
+        assert_eq!(
+            MfaManager::parse_secret_from_uri(uri).unwrap(),
+            "ABCDEF234567"
+        );
+        assert!(MfaManager::parse_secret_from_uri("otpauth://totp/x?issuer=y").is_err());
+    }
+}
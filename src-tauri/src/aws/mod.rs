@@ -1,9 +1,33 @@
 pub mod commands;
 mod aws_cli;
 mod aws_config;
+pub mod aws_error;
+pub mod backend;
+pub mod cancellation;
+mod config_lint;
+pub mod credential_process;
+mod credentials;
+pub mod derived_profile;
+#[cfg(test)]
+mod fake_backend;
+mod ini_document;
 mod keyring_manager;
 mod mfa;
 mod models;
+mod native_client;
+pub mod pid_file;
+mod port_allocator;
+mod profile_cache;
+mod readiness;
+pub mod refresh;
 mod session_config;
 mod session;
+mod session_log;
+mod sessions_file;
+pub mod session_registry;
+mod settings;
+mod sigv4;
+mod sso;
+mod supervisor;
+mod tooling;
 mod utils;
\ No newline at end of file
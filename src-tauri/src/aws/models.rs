@@ -10,14 +10,24 @@ pub struct SessionCredentials {
 }
 
 impl SessionCredentials {
-    /// Check if credentials are still valid (not expired with 5-minute buffer)
-    pub fn is_valid(&self) -> bool {
+    /// Check if credentials are still valid (not expiring within `buffer`)
+    pub fn is_valid(&self, buffer: Duration) -> bool {
         let now = OffsetDateTime::now_utc();
-        let buffer = Duration::minutes(5);
         self.expiration > now + buffer
     }
 }
 
+/// Parameters of an `sts assume-role` call.
+#[derive(Debug, Clone)]
+pub struct AssumeRoleRequest {
+    pub role_arn: String,
+    pub role_session_name: String,
+    pub serial_number: Option<String>,
+    pub token_code: Option<String>,
+    pub duration_seconds: Option<u32>,
+    pub external_id: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AwsCliOutput {
     #[serde(rename = "VirtualMFADevice")]
@@ -28,10 +38,42 @@ pub struct AwsCliOutput {
     pub mfa_devices: Option<Vec<MfaDevice>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VirtualMfaDevice {
     #[serde(rename = "SerialNumber")]
     pub serial_number: String,
+    /// The secret, when the device was bootstrapped with a seed instead of a QR code image.
+    #[serde(rename = "Base32StringSeed", default)]
+    pub base32_string_seed: Option<String>,
+}
+
+/// Output of `sso get-role-credentials` and of the SSO portal API.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SsoRoleCredentialsOutput {
+    pub role_credentials: SsoRoleCredentials,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SsoRoleCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: String,
+    /// Milliseconds since the Unix epoch
+    pub expiration: i64,
+}
+
+impl SsoRoleCredentials {
+    pub fn into_session_credentials(self) -> SessionCredentials {
+        SessionCredentials {
+            access_key_id: self.access_key_id,
+            secret_access_key: self.secret_access_key,
+            session_token: self.session_token,
+            expiration: OffsetDateTime::from_unix_timestamp(self.expiration / 1000)
+                .unwrap_or_else(|_| OffsetDateTime::now_utc()),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,7 +88,7 @@ pub struct AwsCredentials {
     pub expiration: OffsetDateTime,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MfaDevice {
     #[serde(rename = "UserName")]
     pub user_name: String,
@@ -55,3 +97,20 @@ pub struct MfaDevice {
     #[serde(rename = "EnableDate")]
     pub enable_date: OffsetDateTime,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallerIdentity {
+    #[serde(rename = "UserId")]
+    pub user_id: String,
+    #[serde(rename = "Account")]
+    pub account: String,
+    #[serde(rename = "Arn")]
+    pub arn: String,
+}
+
+impl CallerIdentity {
+    /// The user name is the last segment of the ARN
+    pub fn username(&self) -> String {
+        self.arn.split('/').next_back().unwrap_or(&self.arn).to_string()
+    }
+}
@@ -0,0 +1,817 @@
+use super::aws_cli::AwsCli;
+use super::aws_config::AwsConfig;
+use super::aws_error::with_retry;
+use super::backend::AwsBackend;
+use super::cancellation;
+use super::models::{
+    AssumeRoleRequest, CallerIdentity, MfaDevice, SessionCredentials, SsoRoleCredentialsOutput,
+    VirtualMfaDevice,
+};
+use super::settings::AwsSettings;
+use super::sigv4::{form_encode, SigV4, SigningCredentials};
+use super::utils::{get_aws_config_path, get_aws_credentials_path};
+use crate::error::{AppError, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ini::Ini;
+use std::fs;
+use tauri_plugin_http::reqwest::Client;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use url::Url;
+
+/// Global STS and IAM endpoints are both signed for this region.
+const SIGNING_REGION: &str = "us-east-1";
+const CONTENT_TYPE: &str = "application/x-www-form-urlencoded; charset=utf-8";
+
+#[derive(Debug, Clone, Copy)]
+enum Service {
+    Sts,
+    Iam,
+}
+
+impl Service {
+    fn name(self) -> &'static str {
+        match self {
+            Service::Sts => "sts",
+            Service::Iam => "iam",
+        }
+    }
+
+    fn api_version(self) -> &'static str {
+        match self {
+            Service::Sts => "2011-06-15",
+            Service::Iam => "2010-05-08",
+        }
+    }
+}
+
+/// Backend calling the STS and IAM query APIs directly over HTTPS.
+/// Requests are signed with the profile's static keys from the shared AWS files. Profiles it
+/// cannot serve, such as SSO, `credential_process` or custom endpoint profiles, go through the
+/// CLI instead.
+pub struct NativeBackend {
+    client: Client,
+    sts_endpoint: String,
+    iam_endpoint: String,
+    sso_portal_endpoint: Option<String>,
+}
+
+impl NativeBackend {
+    pub fn new(sts_endpoint: &str, iam_endpoint: &str, sso_portal_endpoint: Option<&str>) -> Self {
+        Self {
+            client: Client::new(),
+            sts_endpoint: sts_endpoint.to_string(),
+            iam_endpoint: iam_endpoint.to_string(),
+            sso_portal_endpoint: sso_portal_endpoint.map(|s| s.to_string()),
+        }
+    }
+
+    /// Whether calls for the profile have to go through the CLI, which resolves every kind of
+    /// credentials and honors custom endpoints and CA bundles.
+    fn needs_cli(profile: Option<&str>) -> bool {
+        if load_signing_credentials(profile).is_err() {
+            return true;
+        }
+        if ["AWS_ENDPOINT_URL", "AWS_CA_BUNDLE"]
+            .iter()
+            .any(|var| std::env::var_os(var).is_some())
+        {
+            return true;
+        }
+
+        let name = profile
+            .map(String::from)
+            .or_else(|| std::env::var("AWS_PROFILE").ok())
+            .unwrap_or_else(|| "default".to_string());
+        AwsConfig::get_profile(&name).is_ok_and(|p| {
+            p.endpoint_url.is_some() || p.ca_bundle.is_some() || p.services.is_some()
+        })
+    }
+
+    /// Call the IAM Identity Center portal, which takes a bearer token instead of a signature.
+    async fn get_portal_credentials(
+        &self,
+        account_id: &str,
+        role_name: &str,
+        access_token: &str,
+        region: &str,
+    ) -> Result<SessionCredentials> {
+        let endpoint = self
+            .sso_portal_endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://portal.sso.{}.amazonaws.com", region));
+        let mut url = Url::parse(&endpoint)
+            .map_err(|e| AppError::Custom(format!("Invalid endpoint '{}': {}", endpoint, e)))?
+            .join("federation/credentials")
+            .map_err(|e| AppError::Custom(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("account_id", account_id)
+            .append_pair("role_name", role_name);
+
+        let operation = "sso get-role-credentials";
+        let timeout = AwsSettings::load().command_timeout(operation);
+        let request = self
+            .client
+            .get(url)
+            .header("x-amz-sso_bearer_token", access_token);
+
+        let (status, error_type, text) = cancellation::guard(operation, timeout, async {
+            let response = request
+                .send()
+                .await
+                .map_err(|e| AppError::Http(e.to_string()))?;
+            let status = response.status();
+            let error_type = response
+                .headers()
+                .get("x-amzn-errortype")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.split(':').next().unwrap_or(value).to_string());
+            let text = response
+                .text()
+                .await
+                .map_err(|e| AppError::Http(e.to_string()))?;
+            Ok((status, error_type, text))
+        })
+        .await?;
+
+        if !status.is_success() {
+            let message = serde_json::from_str::<serde_json::Value>(&text)
+                .ok()
+                .and_then(|body| {
+                    body.get("message")
+                        .and_then(|m| m.as_str())
+                        .map(String::from)
+                })
+                .unwrap_or(text);
+            return Err(AppError::Aws(format!(
+                "An error occurred ({}) when calling the GetRoleCredentials operation: {}",
+                error_type.unwrap_or_else(|| status.to_string()),
+                message
+            )));
+        }
+
+        let parsed: SsoRoleCredentialsOutput = serde_json::from_str(&text)?;
+        Ok(parsed.role_credentials.into_session_credentials())
+    }
+
+    async fn call(
+        &self,
+        service: Service,
+        action: &str,
+        params: &[(&str, String)],
+        profile: Option<&str>,
+    ) -> Result<String> {
+        let operation = format!("{} {}", service.name(), kebab_case(action));
+
+        with_retry(&operation, || {
+            self.send(service, action, params, profile, &operation)
+        })
+        .await
+    }
+
+    /// Sign and send one request. Signing happens per attempt since signatures are dated.
+    async fn send(
+        &self,
+        service: Service,
+        action: &str,
+        params: &[(&str, String)],
+        profile: Option<&str>,
+        operation: &str,
+    ) -> Result<String> {
+        let endpoint = match service {
+            Service::Sts => &self.sts_endpoint,
+            Service::Iam => &self.iam_endpoint,
+        };
+        let url = Url::parse(endpoint)
+            .map_err(|e| AppError::Custom(format!("Invalid endpoint '{}': {}", endpoint, e)))?;
+
+        let mut form = vec![
+            ("Action", action.to_string()),
+            ("Version", service.api_version().to_string()),
+        ];
+        form.extend(params.iter().cloned());
+        let body = form_encode(&form);
+
+        let credentials = load_signing_credentials(profile)?;
+        let headers = vec![("content-type".to_string(), CONTENT_TYPE.to_string())];
+        let signer = SigV4 {
+            credentials: &credentials,
+            region: SIGNING_REGION,
+            service: service.name(),
+        };
+        let signature = signer.sign(
+            "POST",
+            &url,
+            &headers,
+            body.as_bytes(),
+            OffsetDateTime::now_utc(),
+        );
+
+        let mut request = self.client.post(url).body(body);
+        for (name, value) in headers.iter().chain(signature.iter()) {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        let timeout = AwsSettings::load().command_timeout(operation);
+        let (status, text) = cancellation::guard(operation, timeout, async {
+            let response = request
+                .send()
+                .await
+                .map_err(|e| AppError::Http(e.to_string()))?;
+            let status = response.status();
+            let text = response
+                .text()
+                .await
+                .map_err(|e| AppError::Http(e.to_string()))?;
+            Ok((status, text))
+        })
+        .await?;
+
+        if status.is_success() {
+            return Ok(text);
+        }
+
+        let code = xml_text(&text, "Code").unwrap_or_else(|| status.to_string());
+        let message = xml_text(&text, "Message").unwrap_or_default();
+        Err(AppError::Aws(format!(
+            "An error occurred ({}) when calling the {} operation: {}",
+            code, action, message
+        )))
+    }
+}
+
+#[async_trait]
+impl AwsBackend for NativeBackend {
+    async fn create_virtual_mfa_device(
+        &self,
+        username: &str,
+        outfile: &str,
+        profile: Option<&str>,
+    ) -> Result<VirtualMfaDevice> {
+        if Self::needs_cli(profile) {
+            return AwsCli
+                .create_virtual_mfa_device(username, outfile, profile)
+                .await;
+        }
+
+        let params = [("VirtualMFADeviceName", username.to_string())];
+        let body = self
+            .call(Service::Iam, "CreateVirtualMFADevice", &params, profile)
+            .await?;
+
+        let serial_number = required(&body, "SerialNumber")?;
+        let qr_code: String = required(&body, "QRCodePNG")?
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let png = STANDARD
+            .decode(qr_code)
+            .map_err(|e| AppError::QrCode(format!("Invalid QR code in response: {}", e)))?;
+        fs::write(outfile, png)?;
+
+        Ok(VirtualMfaDevice {
+            serial_number,
+            base32_string_seed: None,
+        })
+    }
+
+    async fn enable_mfa_device(
+        &self,
+        username: &str,
+        serial_number: &str,
+        code1: &str,
+        code2: &str,
+        profile: Option<&str>,
+    ) -> Result<()> {
+        if Self::needs_cli(profile) {
+            return AwsCli
+                .enable_mfa_device(username, serial_number, code1, code2, profile)
+                .await;
+        }
+
+        let params = [
+            ("UserName", username.to_string()),
+            ("SerialNumber", serial_number.to_string()),
+            ("AuthenticationCode1", code1.to_string()),
+            ("AuthenticationCode2", code2.to_string()),
+        ];
+        self.call(Service::Iam, "EnableMFADevice", &params, profile)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_session_token(
+        &self,
+        serial_number: &str,
+        token_code: &str,
+        profile: Option<&str>,
+    ) -> Result<SessionCredentials> {
+        if Self::needs_cli(profile) {
+            return AwsCli
+                .get_session_token(serial_number, token_code, profile)
+                .await;
+        }
+
+        let params = [
+            ("SerialNumber", serial_number.to_string()),
+            ("TokenCode", token_code.to_string()),
+        ];
+        let body = self
+            .call(Service::Sts, "GetSessionToken", &params, profile)
+            .await?;
+
+        parse_credentials(&body)
+    }
+
+    async fn assume_role(
+        &self,
+        request: &AssumeRoleRequest,
+        profile: Option<&str>,
+    ) -> Result<SessionCredentials> {
+        if Self::needs_cli(profile) {
+            return AwsCli.assume_role(request, profile).await;
+        }
+
+        let mut params = vec![
+            ("RoleArn", request.role_arn.clone()),
+            ("RoleSessionName", request.role_session_name.clone()),
+        ];
+        if let (Some(serial), Some(code)) = (&request.serial_number, &request.token_code) {
+            params.push(("SerialNumber", serial.clone()));
+            params.push(("TokenCode", code.clone()));
+        }
+        if let Some(duration) = request.duration_seconds {
+            params.push(("DurationSeconds", duration.to_string()));
+        }
+        if let Some(external_id) = &request.external_id {
+            params.push(("ExternalId", external_id.clone()));
+        }
+
+        let body = self
+            .call(Service::Sts, "AssumeRole", &params, profile)
+            .await?;
+
+        parse_credentials(&body)
+    }
+
+    async fn get_role_credentials(
+        &self,
+        account_id: &str,
+        role_name: &str,
+        access_token: &str,
+        region: &str,
+    ) -> Result<SessionCredentials> {
+        with_retry("sso get-role-credentials", || {
+            self.get_portal_credentials(account_id, role_name, access_token, region)
+        })
+        .await
+    }
+
+    async fn list_mfa_devices(
+        &self,
+        username: &str,
+        profile: Option<&str>,
+    ) -> Result<Vec<MfaDevice>> {
+        if Self::needs_cli(profile) {
+            return AwsCli.list_mfa_devices(username, profile).await;
+        }
+
+        let mut devices = Vec::new();
+        let mut marker = None;
+
+        // IAM pages the devices, continuing after the marker of a truncated page
+        loop {
+            let mut params = vec![("UserName", username.to_string())];
+            params.extend(marker.map(|marker| ("Marker", marker)));
+            let body = self
+                .call(Service::Iam, "ListMFADevices", &params, profile)
+                .await?;
+
+            for member in xml_members(&body, "MFADevices") {
+                devices.push(MfaDevice {
+                    user_name: required(member, "UserName")?,
+                    serial_number: required(member, "SerialNumber")?,
+                    enable_date: parse_timestamp(&required(member, "EnableDate")?)?,
+                });
+            }
+
+            if xml_text(&body, "IsTruncated").as_deref() != Some("true") {
+                return Ok(devices);
+            }
+            marker = Some(required(&body, "Marker")?);
+        }
+    }
+
+    async fn get_caller_identity(&self, profile: Option<&str>) -> Result<CallerIdentity> {
+        if Self::needs_cli(profile) {
+            return AwsCli.get_caller_identity(profile).await;
+        }
+
+        let body = self
+            .call(Service::Sts, "GetCallerIdentity", &[], profile)
+            .await?;
+
+        Ok(CallerIdentity {
+            user_id: required(&body, "UserId")?,
+            account: required(&body, "Account")?,
+            arn: required(&body, "Arn")?,
+        })
+    }
+}
+
+/// Read the static keys of a profile from the shared credentials file, then the config file.
+/// Without a profile the standard environment variables are used, falling back to `default`.
+fn load_signing_credentials(profile: Option<&str>) -> Result<SigningCredentials> {
+    if profile.is_none() {
+        if let (Ok(access_key_id), Ok(secret_access_key)) = (
+            std::env::var("AWS_ACCESS_KEY_ID"),
+            std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(SigningCredentials {
+                access_key_id,
+                secret_access_key,
+                session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+    }
+
+    let profile = profile.unwrap_or("default");
+    let config_section = if profile == "default" {
+        "default".to_string()
+    } else {
+        format!("profile {}", profile)
+    };
+
+    let sources = [
+        (get_aws_credentials_path()?, profile.to_string()),
+        (get_aws_config_path()?, config_section),
+    ];
+
+    for (path, section) in sources {
+        let Ok(ini) = Ini::load_from_file(&path) else {
+            continue;
+        };
+        let Some(properties) = ini.section(Some(section.as_str())) else {
+            continue;
+        };
+
+        if let (Some(access_key_id), Some(secret_access_key)) = (
+            properties.get("aws_access_key_id"),
+            properties.get("aws_secret_access_key"),
+        ) {
+            return Ok(SigningCredentials {
+                access_key_id: access_key_id.to_string(),
+                secret_access_key: secret_access_key.to_string(),
+                session_token: properties.get("aws_session_token").map(|s| s.to_string()),
+            });
+        }
+    }
+
+    Err(AppError::Custom(format!(
+        "No access keys found for profile '{}'",
+        profile
+    )))
+}
+
+/// `CreateVirtualMFADevice` becomes `create-virtual-mfa-device`, matching the CLI names.
+fn kebab_case(action: &str) -> String {
+    let chars: Vec<char> = action.chars().collect();
+    let mut name = String::with_capacity(action.len() + 4);
+
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 && c.is_uppercase() {
+            let prev_lower = chars[i - 1].is_lowercase();
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev_lower || (chars[i - 1].is_uppercase() && next_lower) {
+                name.push('-');
+            }
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+
+    name
+}
+
+fn parse_credentials(body: &str) -> Result<SessionCredentials> {
+    Ok(SessionCredentials {
+        access_key_id: required(body, "AccessKeyId")?,
+        secret_access_key: required(body, "SecretAccessKey")?,
+        session_token: required(body, "SessionToken")?,
+        expiration: parse_timestamp(&required(body, "Expiration")?)?,
+    })
+}
+
+fn parse_timestamp(value: &str) -> Result<OffsetDateTime> {
+    OffsetDateTime::parse(value, &Rfc3339)
+        .map_err(|e| AppError::Aws(format!("Invalid timestamp '{}': {}", value, e)))
+}
+
+fn required(xml: &str, tag: &str) -> Result<String> {
+    xml_text(xml, tag).ok_or_else(|| AppError::Aws(format!("No {} in response", tag)))
+}
+
+/// Text content of the first `<tag>` element.
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    xml_element(xml, tag).map(unescape)
+}
+
+/// Raw content of the `<member>` elements inside the first `<tag>` element.
+fn xml_members<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let mut members = Vec::new();
+    let mut rest = xml_element(xml, tag).unwrap_or_default();
+
+    while let Some(member) = xml_element(rest, "member") {
+        members.push(member);
+        let end = rest.find("</member>").unwrap_or(rest.len());
+        rest = &rest[(end + "</member>".len()).min(rest.len())..];
+    }
+
+    members
+}
+
+fn xml_element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(&xml[start..end])
+}
+
+fn unescape(text: &str) -> String {
+    text.trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::aws_error::AwsErrorKind;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const CALLER_IDENTITY: &str = r#"<GetCallerIdentityResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <GetCallerIdentityResult>
+    <Arn>arn:aws:iam::123456789012:user/alice</Arn>
+    <UserId>AIDAEXAMPLE</UserId>
+    <Account>123456789012</Account>
+  </GetCallerIdentityResult>
+</GetCallerIdentityResponse>"#;
+
+    const SESSION_TOKEN: &str = r#"<GetSessionTokenResponse>
+  <GetSessionTokenResult>
+    <Credentials>
+      <SessionToken>token&amp;more</SessionToken>
+      <SecretAccessKey>secret</SecretAccessKey>
+      <Expiration>2030-01-01T12:00:00Z</Expiration>
+      <AccessKeyId>ASIAEXAMPLE</AccessKeyId>
+    </Credentials>
+  </GetSessionTokenResult>
+</GetSessionTokenResponse>"#;
+
+    const MFA_DEVICES: &str = r#"<ListMFADevicesResponse>
+  <ListMFADevicesResult>
+    <IsTruncated>false</IsTruncated>
+    <MFADevices>
+      <member>
+        <UserName>alice</UserName>
+        <SerialNumber>arn:aws:iam::123456789012:mfa/alice</SerialNumber>
+        <EnableDate>2024-03-01T10:00:00Z</EnableDate>
+      </member>
+      <member>
+        <UserName>alice</UserName>
+        <SerialNumber>arn:aws:iam::123456789012:mfa/backup</SerialNumber>
+        <EnableDate>2024-04-01T10:00:00Z</EnableDate>
+      </member>
+    </MFADevices>
+  </ListMFADevicesResult>
+</ListMFADevicesResponse>"#;
+
+    const MFA_DEVICES_FIRST_PAGE: &str = r#"<ListMFADevicesResponse>
+  <ListMFADevicesResult>
+    <IsTruncated>true</IsTruncated>
+    <Marker>page+2</Marker>
+    <MFADevices>
+      <member>
+        <UserName>alice</UserName>
+        <SerialNumber>arn:aws:iam::123456789012:mfa/old</SerialNumber>
+        <EnableDate>2023-01-01T10:00:00Z</EnableDate>
+      </member>
+    </MFADevices>
+  </ListMFADevicesResult>
+</ListMFADevicesResponse>"#;
+
+    const ACCESS_DENIED: &str = r#"<ErrorResponse>
+  <Error>
+    <Type>Sender</Type>
+    <Code>AccessDenied</Code>
+    <Message>User &quot;alice&quot; is not authorized</Message>
+  </Error>
+  <RequestId>1</RequestId>
+</ErrorResponse>"#;
+
+    const THROTTLED: &str = r#"<ErrorResponse>
+  <Error>
+    <Code>Throttling</Code>
+    <Message>Rate exceeded</Message>
+  </Error>
+</ErrorResponse>"#;
+
+    /// Serve the responses in order, one connection each, recording the requests.
+    async fn stub(responses: Vec<(u16, &'static str)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut socket).await;
+                recorded.lock().unwrap().push(request);
+
+                let response = format!(
+                    "HTTP/1.1 {} Stub\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (endpoint, requests)
+    }
+
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+        let mut data = Vec::new();
+        let mut buffer = [0; 4096];
+
+        loop {
+            let read = socket.read(&mut buffer).await.unwrap();
+            data.extend_from_slice(&buffer[..read]);
+
+            let text = String::from_utf8_lossy(&data).to_string();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if data.len() >= end + 4 + length || read == 0 {
+                    return text;
+                }
+            }
+        }
+    }
+
+    /// Sign with keys from the environment, as calls without a profile do. Endpoint overrides
+    /// of the environment would hand the calls to the CLI.
+    fn backend(endpoint: &str) -> NativeBackend {
+        std::env::set_var("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE");
+        std::env::set_var("AWS_SECRET_ACCESS_KEY", "secret");
+        std::env::remove_var("AWS_ENDPOINT_URL");
+        std::env::remove_var("AWS_CA_BUNDLE");
+        NativeBackend::new(endpoint, endpoint, None)
+    }
+
+    #[tokio::test]
+    async fn call_sends_signed_form() {
+        let (endpoint, requests) = stub(vec![(200, CALLER_IDENTITY)]).await;
+
+        let body = backend(&endpoint)
+            .call(Service::Sts, "GetCallerIdentity", &[], None)
+            .await
+            .unwrap();
+
+        assert_eq!(required(&body, "Account").unwrap(), "123456789012");
+        let request = requests.lock().unwrap()[0].to_lowercase();
+        assert!(request.starts_with("post / http/1.1"));
+        assert!(request.contains("authorization: aws4-hmac-sha256 credential=akidexample/"));
+        assert!(request.contains("/us-east-1/sts/aws4_request"));
+        assert!(request.ends_with("action=getcalleridentity&version=2011-06-15"));
+    }
+
+    #[tokio::test]
+    async fn call_reports_error_code() {
+        let (endpoint, requests) = stub(vec![(403, ACCESS_DENIED)]).await;
+
+        let error = backend(&endpoint)
+            .call(Service::Iam, "ListMFADevices", &[], None)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "AWS error: An error occurred (AccessDenied) when calling the ListMFADevices \
+             operation: User \"alice\" is not authorized"
+        );
+        assert_eq!(error.aws_kind(), Some(AwsErrorKind::AccessDenied));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn call_retries_throttling() {
+        let (endpoint, requests) = stub(vec![(400, THROTTLED), (200, CALLER_IDENTITY)]).await;
+
+        let body = backend(&endpoint)
+            .call(Service::Sts, "GetCallerIdentity", &[], None)
+            .await
+            .unwrap();
+
+        assert_eq!(required(&body, "UserId").unwrap(), "AIDAEXAMPLE");
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn call_sends_non_idempotent_actions_once() {
+        let (endpoint, requests) = stub(vec![(400, THROTTLED), (200, "")]).await;
+
+        let error = backend(&endpoint)
+            .call(Service::Iam, "EnableMFADevice", &[], None)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.aws_kind(), Some(AwsErrorKind::Throttling));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn lists_every_page_of_mfa_devices() {
+        let (endpoint, requests) =
+            stub(vec![(200, MFA_DEVICES_FIRST_PAGE), (200, MFA_DEVICES)]).await;
+
+        let devices = backend(&endpoint)
+            .list_mfa_devices("alice", None)
+            .await
+            .unwrap();
+
+        let serials: Vec<_> = devices.iter().map(|d| d.serial_number.as_str()).collect();
+        assert_eq!(
+            serials,
+            [
+                "arn:aws:iam::123456789012:mfa/old",
+                "arn:aws:iam::123456789012:mfa/alice",
+                "arn:aws:iam::123456789012:mfa/backup"
+            ]
+        );
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].contains("Marker="));
+        assert!(requests[1].ends_with("&UserName=alice&Marker=page%2B2"));
+    }
+
+    #[test]
+    fn parses_credentials() {
+        let credentials = parse_credentials(SESSION_TOKEN).unwrap();
+
+        assert_eq!(credentials.access_key_id, "ASIAEXAMPLE");
+        assert_eq!(credentials.secret_access_key, "secret");
+        assert_eq!(credentials.session_token, "token&more");
+        assert_eq!(credentials.expiration.year(), 2030);
+    }
+
+    #[test]
+    fn parses_members() {
+        let members = xml_members(MFA_DEVICES, "MFADevices");
+
+        assert_eq!(members.len(), 2);
+        assert_eq!(
+            xml_text(members[1], "SerialNumber").as_deref(),
+            Some("arn:aws:iam::123456789012:mfa/backup")
+        );
+        assert!(xml_members(CALLER_IDENTITY, "MFADevices").is_empty());
+    }
+
+    #[test]
+    fn reports_missing_elements() {
+        assert!(parse_credentials(CALLER_IDENTITY).is_err());
+        assert_eq!(xml_text("<Code>open", "Code"), None);
+        assert_eq!(xml_text("<Code></Code>", "Code").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn unescapes_entities_once() {
+        assert_eq!(unescape("a &amp;lt; b &lt; c &amp; d"), "a &lt; b < c & d");
+    }
+
+    #[test]
+    fn names_actions_like_the_cli() {
+        assert_eq!(
+            kebab_case("CreateVirtualMFADevice"),
+            "create-virtual-mfa-device"
+        );
+        assert_eq!(kebab_case("ListMFADevices"), "list-mfa-devices");
+        assert_eq!(kebab_case("GetCallerIdentity"), "get-caller-identity");
+    }
+}
@@ -0,0 +1,158 @@
+use crate::util::dirs::get_user_pid_file;
+use crate::util::process::{is_tree_running, kill_tree, process_name};
+use std::fs;
+
+const SESSION_PROCESSES: [&str; 2] = ["aws", "session-manager-plugin"];
+/// Linux cuts process names down to this many characters.
+const TRUNCATED_NAME_LEN: usize = 15;
+
+/// Records the pids of running session processes so a later launch can clean up
+/// after a crash.
+pub struct PidFile;
+
+impl PidFile {
+    pub fn save(pids: &[u32]) {
+        let path = get_user_pid_file();
+
+        if pids.is_empty() {
+            let _ = fs::remove_file(path);
+            return;
+        }
+
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+
+        let content: Vec<String> = pids.iter().map(|pid| pid.to_string()).collect();
+        let _ = fs::write(path, content.join("\n"));
+    }
+
+    /// Terminate session processes left behind by a previous run.
+    pub fn reap_stale() {
+        let Ok(content) = fs::read_to_string(get_user_pid_file()) else {
+            return;
+        };
+
+        for pid in content
+            .lines()
+            .filter_map(|line| line.trim().parse::<u32>().ok())
+        {
+            match process_name(pid) {
+                Some(name) if is_session_process(&name) => {
+                    log::info!("Terminating stale session process {} ({})", pid, name);
+                    kill_tree(pid);
+                }
+                // The pid was reused by an unrelated process
+                Some(_) => {}
+                // The aws process is gone, but the plugin may still run in its process group
+                None if is_tree_running(pid) => {
+                    log::info!(
+                        "Terminating the stale process group of session process {}",
+                        pid
+                    );
+                    kill_tree(pid);
+                }
+                None => {}
+            }
+        }
+
+        Self::save(&[]);
+    }
+}
+
+fn is_session_process(name: &str) -> bool {
+    let name = name.to_lowercase();
+    let name = name.trim_end_matches(".exe");
+
+    SESSION_PROCESSES.iter().any(|process| {
+        *process == name || (name.len() == TRUNCATED_NAME_LEN && process.starts_with(name))
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::aws::fake_backend::use_offline_environment;
+    use std::io::Read;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command, Stdio};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    fn spawn_group(program: &str, args: &[&str]) -> Child {
+        Command::new(program)
+            .args(args)
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap()
+    }
+
+    /// Whether the process exists and is not a zombie waiting for its parent.
+    fn is_alive(pid: u32) -> bool {
+        Command::new("ps")
+            .args(["-p", &pid.to_string(), "-o", "stat="])
+            .output()
+            .is_ok_and(|output| {
+                let stat = String::from_utf8_lossy(&output.stdout);
+                !stat.trim().is_empty() && !stat.trim().starts_with('Z')
+            })
+    }
+
+    fn wait_until_gone(pid: u32) -> bool {
+        for _ in 0..50 {
+            if !is_alive(pid) {
+                return true;
+            }
+            sleep(Duration::from_millis(100));
+        }
+        false
+    }
+
+    #[test]
+    fn reaps_stale_plugins_and_orphaned_groups() {
+        use_offline_environment();
+        let dir = std::env::temp_dir().join(format!("akaw-reap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let plugin_path = dir.join("session-manager-plugin");
+        fs::copy("/bin/sleep", &plugin_path).unwrap();
+
+        let mut plugin = spawn_group(plugin_path.to_str().unwrap(), &["30"]);
+
+        // A session whose aws process exited and left its plugin running in the group
+        let mut leader = spawn_group("sh", &["-c", "sleep 30 >/dev/null & echo $!"]);
+        let mut orphan = String::new();
+        leader
+            .stdout
+            .take()
+            .unwrap()
+            .read_to_string(&mut orphan)
+            .unwrap();
+        leader.wait().unwrap();
+        let orphan: u32 = orphan.trim().parse().unwrap();
+
+        let mut unrelated = spawn_group("sleep", &["30"]);
+
+        PidFile::save(&[plugin.id(), leader.id(), unrelated.id()]);
+        PidFile::reap_stale();
+
+        plugin.wait().unwrap();
+        assert!(wait_until_gone(orphan));
+        assert!(unrelated.try_wait().unwrap().is_none());
+        assert!(!get_user_pid_file().exists());
+
+        unrelated.kill().unwrap();
+        unrelated.wait().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn matches_names_the_os_shortened() {
+        assert!(is_session_process("aws"));
+        assert!(is_session_process("AWS.EXE"));
+        assert!(is_session_process("session-manager"));
+        assert!(is_session_process("session-manager-plugin.exe"));
+        assert!(!is_session_process("session"));
+        assert!(!is_session_process("sleep"));
+    }
+}
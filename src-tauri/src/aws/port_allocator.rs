@@ -0,0 +1,56 @@
+use super::session::SessionManager;
+use super::settings::AwsSettings;
+use crate::error::{AppError, Result};
+use crate::util::dirs::get_user_ports_file;
+use std::collections::HashMap;
+use std::fs;
+
+/// Picks local ports for profiles running in auto port mode and remembers the last one used,
+/// so a profile keeps the same port across restarts while it stays free.
+pub struct PortAllocator;
+
+impl PortAllocator {
+    pub fn allocate(key: &str, preferred: u16, settings: &AwsSettings) -> Result<u16> {
+        if let Some(&remembered) = Self::load().get(key) {
+            if !SessionManager::is_port_in_use(remembered) {
+                return Ok(remembered);
+            }
+        }
+
+        if !SessionManager::is_port_in_use(preferred) {
+            return Ok(preferred);
+        }
+
+        let (start, end) = (settings.port_range_start, settings.port_range_end);
+
+        (start..=end)
+            .find(|port| !SessionManager::is_port_in_use(*port))
+            .ok_or_else(|| {
+                AppError::Custom(format!("No free local port between {} and {}", start, end))
+            })
+    }
+
+    pub fn remember(key: &str, port: u16) -> Result<()> {
+        let mut ports = Self::load();
+
+        if ports.get(key) == Some(&port) {
+            return Ok(());
+        }
+
+        ports.insert(key.to_string(), port);
+
+        let path = get_user_ports_file();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&ports)?)?;
+        Ok(())
+    }
+
+    fn load() -> HashMap<String, u16> {
+        fs::read_to_string(get_user_ports_file())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,132 @@
+use super::aws_config::AwsConfig;
+use super::backend::SharedBackend;
+use super::mfa::MfaManager;
+use super::settings::AwsSettings;
+use crate::error::Result;
+use crate::util::dirs::get_user_profile_cache_file;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Identity details of a profile as last fetched from AWS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedProfile {
+    pub username: String,
+    pub mfa_serial: Option<String>,
+    pub fetched_at: i64,
+}
+
+impl CachedProfile {
+    fn is_fresh(&self, ttl_secs: u64) -> bool {
+        OffsetDateTime::now_utc().unix_timestamp() - self.fetched_at < ttl_secs as i64
+    }
+}
+
+/// Disk cache of the per-profile lookups behind the profile list, so opening the list does not
+/// cost AWS round-trips every time and still shows the last known details when offline.
+pub struct ProfileCache;
+
+impl ProfileCache {
+    /// Details for each profile, fetching the missing or expired ones concurrently.
+    /// With `force` every profile is fetched again. A failed fetch keeps the stale entry.
+    pub async fn lookup(
+        backend: SharedBackend,
+        profiles: Vec<String>,
+        force: bool,
+    ) -> HashMap<String, CachedProfile> {
+        let settings = AwsSettings::load();
+        let mut cache = Self::load();
+
+        let semaphore = Arc::new(Semaphore::new(settings.profile_lookup_concurrency.max(1)));
+        let mut tasks = JoinSet::new();
+
+        for profile in profiles {
+            let fresh = cache
+                .get(&profile)
+                .is_some_and(|cached| cached.is_fresh(settings.profile_cache_ttl_secs));
+            if fresh && !force {
+                continue;
+            }
+
+            let backend = backend.clone();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let result = Self::fetch(backend, &profile).await;
+                (profile, result)
+            });
+        }
+
+        let mut changed = false;
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((profile, Ok(details))) => {
+                    cache.insert(profile, details);
+                    changed = true;
+                }
+                Ok((profile, Err(e))) => {
+                    log::warn!("Failed to look up profile '{}': {}", profile, e);
+                }
+                Err(e) => log::error!("Profile lookup task failed: {}", e),
+            }
+        }
+
+        if changed {
+            if let Err(e) = Self::save(&cache) {
+                log::warn!("Failed to save the profile cache: {}", e);
+            }
+        }
+
+        cache
+    }
+
+    /// Drop the cached details of a profile, or of all profiles.
+    pub fn invalidate(profile: Option<&str>) -> Result<()> {
+        let mut cache = Self::load();
+
+        match profile {
+            Some(profile) => {
+                if cache.remove(profile).is_none() {
+                    return Ok(());
+                }
+            }
+            None => cache.clear(),
+        }
+
+        Self::save(&cache)
+    }
+
+    async fn fetch(backend: SharedBackend, profile: &str) -> Result<CachedProfile> {
+        let backend = backend.as_ref();
+        let username = AwsConfig::get_username(backend, profile).await?;
+        let mfa_serial = MfaManager::fetch_mfa_serial(backend, &username, profile)
+            .await
+            .ok();
+
+        Ok(CachedProfile {
+            username,
+            mfa_serial,
+            fetched_at: OffsetDateTime::now_utc().unix_timestamp(),
+        })
+    }
+
+    fn load() -> HashMap<String, CachedProfile> {
+        fs::read_to_string(get_user_profile_cache_file())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(cache: &HashMap<String, CachedProfile>) -> Result<()> {
+        let path = get_user_profile_cache_file();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(cache)?)?;
+        Ok(())
+    }
+}
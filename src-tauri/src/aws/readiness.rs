@@ -0,0 +1,66 @@
+use super::session_log::LogStream;
+use super::session_registry::{ChildState, SessionRegistry};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+const READY_MARKER: &str = "Waiting for connections";
+
+#[derive(Debug)]
+pub enum Readiness {
+    Ready,
+    Failed(String),
+}
+
+/// Wait until the plugin reports it is listening or the local port accepts connections.
+/// Fails when the child exits first or the timeout elapses.
+pub async fn wait_until_ready(
+    registry: &SessionRegistry,
+    id: u64,
+    local_port: u16,
+    timeout: Duration,
+) -> Readiness {
+    let deadline = Instant::now() + timeout;
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, local_port));
+
+    loop {
+        let Ok(log) = registry.log(id).await else {
+            return Readiness::Failed("Session was stopped before it became ready".to_string());
+        };
+
+        if log.contains(READY_MARKER) {
+            return Readiness::Ready;
+        }
+
+        if let Some(ChildState::Exited(status)) = registry.poll(id).await {
+            let errors = log.text(Some(LogStream::Stderr));
+            return Readiness::Failed(if errors.is_empty() {
+                format!("Session exited before it became ready ({:?})", status)
+            } else {
+                errors
+            });
+        }
+
+        if let Ok(Ok(_)) = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            return Readiness::Ready;
+        }
+
+        if Instant::now() >= deadline {
+            let errors = log.text(Some(LogStream::Stderr));
+            return Readiness::Failed(if errors.is_empty() {
+                format!(
+                    "Timed out after {}s waiting for port {}",
+                    timeout.as_secs(),
+                    local_port
+                )
+            } else {
+                errors
+            });
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,185 @@
+use super::backend::{AwsBackend, SharedBackend};
+use super::credentials::CredentialManager;
+use super::derived_profile::DerivedProfile;
+use super::keyring_manager::KeyringManager;
+use super::models::SessionCredentials;
+use super::session_registry::{ActiveSession, SessionRegistry};
+use super::settings::AwsSettings;
+use crate::error::Result;
+use crate::util::formatter::log_time_fmt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use time::{Duration as TimeDuration, OffsetDateTime};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+pub const EVENT_CREDENTIALS_REFRESHED: &str = "credentials-refreshed";
+pub const EVENT_CREDENTIALS_REFRESH_FAILED: &str = "credentials-refresh-failed";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RefreshEvent {
+    pub profile: String,
+    pub expiration: Option<String>,
+    pub restarted: Vec<u64>,
+    pub error: Option<String>,
+}
+
+/// Background task that renews the credentials of profiles with running sessions
+/// before they expire and restarts those sessions with the new credentials.
+pub struct CredentialRefresher;
+
+impl CredentialRefresher {
+    pub fn spawn(app: AppHandle) {
+        tauri::async_runtime::spawn(async move {
+            let mut short_lived = HashMap::new();
+            loop {
+                tokio::time::sleep(CHECK_INTERVAL).await;
+                Self::tick(&app, &mut short_lived).await;
+            }
+        });
+    }
+
+    async fn tick(app: &AppHandle, short_lived: &mut HashMap<String, OffsetDateTime>) {
+        if let Err(e) = DerivedProfile::prune_expired() {
+            log::warn!("Failed to prune derived credentials: {}", e);
+        }
+
+        let registry = app.state::<SessionRegistry>();
+        let backend = app.state::<SharedBackend>();
+        let settings = AwsSettings::load();
+        let lead = settings.refresh_lead();
+        let deadline = OffsetDateTime::now_utc() + lead;
+
+        let mut by_profile: HashMap<String, Vec<ActiveSession>> = HashMap::new();
+        for session in registry.list().await {
+            if session.expiration <= deadline {
+                by_profile
+                    .entry(session.profile.clone())
+                    .or_default()
+                    .push(session);
+            }
+        }
+
+        for (profile, sessions) in by_profile {
+            let renewed = Self::renew(backend.as_ref(), &profile, lead, short_lived).await;
+            let credentials = match renewed {
+                Ok(Some(credentials)) => credentials,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::warn!("Failed to refresh credentials for '{}': {}", profile, e);
+                    let _ = app.emit(
+                        EVENT_CREDENTIALS_REFRESH_FAILED,
+                        RefreshEvent {
+                            profile,
+                            expiration: None,
+                            restarted: Vec::new(),
+                            error: Some(e.to_string()),
+                        },
+                    );
+                    continue;
+                }
+            };
+
+            if settings.write_derived_credentials {
+                if let Err(e) = DerivedProfile::write(&profile, &credentials) {
+                    log::warn!("Failed to write derived credentials for '{}': {}", profile, e);
+                }
+            }
+
+            let mut restarted = Vec::new();
+            for session in sessions {
+                match registry.restart(app, session.id, &credentials).await {
+                    Ok(_) => restarted.push(session.id),
+                    Err(e) => log::warn!("Failed to restart session {}: {}", session.id, e),
+                }
+            }
+
+            let _ = app.emit(
+                EVENT_CREDENTIALS_REFRESHED,
+                RefreshEvent {
+                    profile,
+                    expiration: credentials.expiration.format(log_time_fmt()).ok(),
+                    restarted,
+                    error: None,
+                },
+            );
+        }
+    }
+
+    /// New credentials for the sessions of a profile, or `None` when even fresh credentials
+    /// expire within the refresh lead, as restarting with them would gain nothing and repeat on
+    /// every check. Such credentials are reported once and not minted again until they expire.
+    async fn renew(
+        backend: &dyn AwsBackend,
+        profile: &str,
+        lead: TimeDuration,
+        short_lived: &mut HashMap<String, OffsetDateTime>,
+    ) -> Result<Option<SessionCredentials>> {
+        let now = OffsetDateTime::now_utc();
+        let known = short_lived.get(profile);
+        if known.is_some_and(|expiration| *expiration > now) {
+            return Ok(None);
+        }
+
+        let credentials = match KeyringManager::get_session_credentials(profile) {
+            Ok(cached_creds) if cached_creds.is_valid(lead) => cached_creds,
+            _ => CredentialManager::refresh(backend, profile).await?,
+        };
+
+        if credentials.expiration <= now + lead {
+            log::warn!(
+                "New credentials for '{}' expire at {}, within the refresh lead. Its sessions keep running until then",
+                profile,
+                credentials.expiration.format(log_time_fmt()).unwrap_or_default()
+            );
+            short_lived.insert(profile.to_string(), credentials.expiration);
+            return Ok(None);
+        }
+
+        short_lived.remove(profile);
+        Ok(Some(credentials))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::fake_backend::{use_offline_environment, FakeBackend, Operation, SEED};
+
+    fn expiring_in(minutes: i64) -> SessionCredentials {
+        SessionCredentials {
+            access_key_id: "ASIABRIEF".to_string(),
+            secret_access_key: "brief-secret".to_string(),
+            session_token: "brief".to_string(),
+            expiration: OffsetDateTime::now_utc() + TimeDuration::minutes(minutes),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_sessions_on_credentials_that_expire_within_the_lead() {
+        use_offline_environment();
+        KeyringManager::store_secret("brief", SEED).unwrap();
+        let lead = TimeDuration::minutes(15);
+        let backend = FakeBackend::new().with_session_token(expiring_in(10));
+        let mut short_lived = HashMap::new();
+
+        for _ in 0..3 {
+            let renewed = CredentialRefresher::renew(&backend, "brief", lead, &mut short_lived)
+                .await
+                .unwrap();
+            assert!(renewed.is_none());
+        }
+        assert_eq!(backend.calls_to(Operation::GetSessionToken), 1);
+
+        // Once the short credentials are gone, longer ones are minted and used again
+        short_lived.clear();
+        let renewed = CredentialRefresher::renew(&backend, "brief", lead, &mut short_lived)
+            .await
+            .unwrap();
+        assert!(renewed.is_some_and(|credentials| credentials.is_valid(lead)));
+        assert_eq!(backend.calls_to(Operation::GetSessionToken), 2);
+        assert!(short_lived.is_empty());
+    }
+}
@@ -1,25 +1,248 @@
 use crate::error::{AppError, Result};
 use super::models::SessionCredentials;
-use std::process::Stdio;
+use super::session_config::{SessionConfig, REMOTE_HOST_DOCUMENT};
+use super::settings::AwsSettings;
+use super::tooling::Tooling;
+use super::utils::apply_aws_files;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command as StdCommand, Stdio};
+use time::OffsetDateTime;
 use tokio::process::{Child, Command};
 
+pub const INTERACTIVE_COMMAND_DOCUMENT: &str = "AWS-StartInteractiveCommand";
+
 pub struct SessionManager;
 
 impl SessionManager {
     pub async fn start_session(
         profile: &str,
-        target_id: &str,
-        local_port: u16,
-        remote_port: u16,
-        document_name: &str,
+        config: &SessionConfig,
         credentials: &SessionCredentials,
     ) -> Result<Child> {
-        if Self::is_port_in_use(local_port) {
-            return Err(AppError::PortInUse(local_port));
+        if Self::is_port_in_use(config.local_port) {
+            return Err(AppError::PortInUse(config.local_port));
+        }
+
+        let mut cmd = Tooling::aws_command(&AwsSettings::load());
+        Self::apply_credentials(&mut cmd, credentials);
+
+        cmd.args(&["ssm", "start-session", "--target", &config.target]);
+
+        if !config.document_name.is_empty() {
+            cmd.arg("--document-name").arg(&config.document_name);
+        }
+
+        let mut parameters = format!(
+            "portNumber={},localPortNumber={}",
+            config.remote_port, config.local_port
+        );
+
+        // Only the remote host document takes a host, the others reject the parameter
+        if let Some(host) = config
+            .host
+            .as_ref()
+            .filter(|_| config.document_name == REMOTE_HOST_DOCUMENT)
+        {
+            parameters = format!("host={},{}", host, parameters);
+        }
+
+        cmd.args(["--parameters", &parameters]);
+
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+        // Lead a process group so the session-manager-plugin can be terminated along with it
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        // Spawn and return the child process handle
+        let child = cmd
+            .spawn()
+            .map_err(|e| AppError::AwsCli(format!("AWS session '{}' failed: {}", profile, e)))?;
+
+        Ok(child)
+    }
+
+    /// Open an interactive shell on the target in an external terminal window.
+    /// When a command is given it runs through `AWS-StartInteractiveCommand` instead.
+    pub fn start_shell(
+        profile: &str,
+        target_id: &str,
+        command: Option<&str>,
+        terminal: Option<&str>,
+        credentials: &SessionCredentials,
+    ) -> Result<Option<u32>> {
+        let settings = AwsSettings::load();
+        let mut ssm_args = vec![
+            "ssm".to_string(),
+            "start-session".to_string(),
+            "--target".to_string(),
+            target_id.to_string(),
+        ];
+
+        if let Some(command) = command.filter(|c| !c.trim().is_empty()) {
+            let parameters = serde_json::json!({ "command": [command] });
+            ssm_args.extend([
+                "--document-name".to_string(),
+                INTERACTIVE_COMMAND_DOCUMENT.to_string(),
+                "--parameters".to_string(),
+                parameters.to_string(),
+            ]);
+        }
+
+        let mut invocation = Command::new(Tooling::aws_cli(&settings));
+        invocation.args(&ssm_args);
+        Tooling::apply_plugin_path(&mut invocation, &settings);
+        apply_aws_files(&mut invocation);
+        Self::apply_credentials(&mut invocation, credentials);
+
+        let mut cmd = Self::terminal_command(terminal, invocation.as_std())?;
+
+        let child = cmd
+            .spawn()
+            .map_err(|e| AppError::AwsCli(format!("AWS shell '{}' failed: {}", profile, e)))?;
+
+        Ok(child.id())
+    }
+
+    /// Build the command that opens a new terminal window running `invocation`.
+    fn terminal_command(terminal: Option<&str>, invocation: &StdCommand) -> Result<Command> {
+        let program = invocation.get_program().to_string_lossy().to_string();
+        let args: Vec<String> = invocation
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+
+        let mut cmd = if let Some(terminal) = terminal.filter(|t| !t.trim().is_empty()) {
+            let parts = split_shell_words(terminal, !cfg!(target_os = "windows"))?;
+            let mut cmd = Command::new(&parts[0]);
+            cmd.args(&parts[1..]).arg(&program).args(&args);
+            cmd
+        } else if cfg!(target_os = "windows") {
+            // cmd strips quotes and runs `& | >` found in a command line passed to it, so the
+            // invocation goes through a script where every argument is escaped instead
+            let script = Self::write_script("cmd", &Self::cmd_script(&program, &args)?)?;
+            let mut cmd = Command::new("cmd");
+            // The title contains a space so it gets quoted, which is how `start` tells it apart
+            // from the program to run.
+            cmd.args(["/c", "start", "akaw shell", "cmd", "/k", "call"])
+                .arg(script);
+            cmd
+        } else if cfg!(target_os = "macos") {
+            // Terminal does not inherit the environment, so the script exports it
+            let envs: Vec<(String, String)> = invocation
+                .get_envs()
+                .filter_map(|(key, value)| {
+                    Some((
+                        key.to_string_lossy().to_string(),
+                        value?.to_string_lossy().to_string(),
+                    ))
+                })
+                .collect();
+            let script =
+                Self::write_script("command", &Self::shell_script(&program, &args, &envs))?;
+            let mut cmd = Command::new("open");
+            cmd.args(["-a", "Terminal"]).arg(script);
+            return Ok(cmd);
+        } else if cfg!(target_os = "linux") {
+            // gnome-terminal and xfce4-terminal take the command after `-e` as one string, which
+            // xterm and konsole split as well, so the invocation is passed as a quoted line.
+            // Emulators that only take separate arguments need the 'terminal' setting
+            let line: Vec<String> = std::iter::once(&program)
+                .chain(&args)
+                .map(|arg| quote_shell_arg(arg))
+                .collect();
+            let mut cmd = Command::new("x-terminal-emulator");
+            cmd.arg("-e").arg(line.join(" "));
+            cmd
+        } else {
+            return Err(AppError::Custom(
+                "No terminal configured. Set 'terminal' in the aws settings".to_string(),
+            ));
+        };
+
+        for (key, value) in invocation.get_envs() {
+            match value {
+                Some(value) => cmd.env(key, value),
+                None => cmd.env_remove(key),
+            };
         }
 
-        let mut cmd = Command::new("aws");
+        Ok(cmd)
+    }
+
+    /// A batch script running the program. Every argument is quoted for the C runtime and
+    /// then escaped for cmd, so quotes and metacharacters reach the program unchanged.
+    fn cmd_script(program: &str, args: &[String]) -> Result<String> {
+        let mut line = format!("@\"{}\"", program.replace('%', "%%"));
+
+        for arg in args {
+            if arg.contains(['\r', '\n']) {
+                return Err(AppError::Custom(
+                    "Shell commands cannot span several lines".to_string(),
+                ));
+            }
 
+            line.push(' ');
+            for c in quote_windows_arg(arg).chars() {
+                match c {
+                    '%' => line.push_str("%%"),
+                    '^' | '&' | '|' | '<' | '>' | '(' | ')' | '"' => {
+                        line.push('^');
+                        line.push(c);
+                    }
+                    _ => line.push(c),
+                }
+            }
+        }
+
+        // The last line deletes the script once the session ends, leaving the window open
+        Ok(format!(
+            "@setlocal DisableDelayedExpansion\r\n{}\r\n@(goto) 2>nul & del \"%~f0\"\r\n",
+            line
+        ))
+    }
+
+    /// A shell script exporting the environment and running the program. It removes itself
+    /// right away, since it holds the session credentials.
+    fn shell_script(program: &str, args: &[String], envs: &[(String, String)]) -> String {
+        let mut script = String::from("#!/bin/sh\nrm -f -- \"$0\"\n");
+
+        for (key, value) in envs {
+            script.push_str(&format!("export {}={}\n", key, quote_shell_arg(value)));
+        }
+
+        let words: Vec<String> = std::iter::once(program)
+            .chain(args.iter().map(String::as_str))
+            .map(quote_shell_arg)
+            .collect();
+        script.push_str(&format!("exec {}\n", words.join(" ")));
+
+        script
+    }
+
+    /// Write a script to a new file in the temp directory, readable only by the user.
+    fn write_script(extension: &str, content: &str) -> Result<PathBuf> {
+        let nanos = OffsetDateTime::now_utc().unix_timestamp_nanos();
+        let path = std::env::temp_dir().join(format!(
+            "akaw-shell-{}-{}.{}",
+            std::process::id(),
+            nanos,
+            extension
+        ));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o700);
+
+        options.open(&path)?.write_all(content.as_bytes())?;
+
+        Ok(path)
+    }
+
+    fn apply_credentials(cmd: &mut Command, credentials: &SessionCredentials) {
         if !credentials.access_key_id.is_empty() {
             cmd.env("AWS_ACCESS_KEY_ID", &credentials.access_key_id);
         }
@@ -29,29 +252,126 @@ impl SessionManager {
         if !credentials.session_token.is_empty() {
             cmd.env("AWS_SESSION_TOKEN", &credentials.session_token);
         }
+    }
 
-        cmd.args(&["ssm", "start-session", "--target", target_id]);
+    pub fn is_port_in_use(port: u16) -> bool {
+        std::net::TcpListener::bind(("127.0.0.1", port)).is_err()
+    }
+}
 
-        if !document_name.is_empty() {
-            cmd.arg("--document-name").arg(document_name);
+/// Quote an argument the way the Microsoft C runtime splits a command line.
+fn quote_windows_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
         }
+        // Backslashes only escape when they precede a quote
+        let escapes = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.push_str(&"\\".repeat(escapes));
+        quoted.push(c);
+        backslashes = 0;
+    }
 
-        cmd.args(&[
-            "--parameters",
-            &format!("portNumber={},localPortNumber={}", remote_port, local_port),
-        ]);
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
 
-        cmd.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+fn quote_shell_arg(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
 
-        // Spawn and return the child process handle
-        let child = cmd
-            .spawn()
-            .map_err(|e| AppError::AwsCli(format!("AWS session '{}' failed: {}", profile, e)))?;
+/// Split a command line into words like a POSIX shell does, so a quoted path may contain
+/// spaces. Backslashes only escape where they do not separate Windows paths.
+fn split_shell_words(line: &str, escapes: bool) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
 
-        Ok(child)
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let quoted = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => quoted.push(c),
+                        None => return Err(unterminated_quote(line)),
+                    }
+                }
+            }
+            '"' => {
+                let quoted = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') if escapes => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => quoted.push(c),
+                            Some(c) => {
+                                quoted.push('\\');
+                                quoted.push(c);
+                            }
+                            None => return Err(unterminated_quote(line)),
+                        },
+                        Some(c) => quoted.push(c),
+                        None => return Err(unterminated_quote(line)),
+                    }
+                }
+            }
+            '\\' if escapes => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_with(String::new).push(c);
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
     }
+    words.extend(word);
 
-    fn is_port_in_use(port: u16) -> bool {
-        std::net::TcpListener::bind(("127.0.0.1", port)).is_err()
+    if words.is_empty() {
+        return Err(AppError::Custom("'terminal' is empty".to_string()));
+    }
+    Ok(words)
+}
+
+fn unterminated_quote(line: &str) -> AppError {
+    AppError::Custom(format!("'terminal' has an unterminated quote: {}", line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_terminal_settings_like_a_shell() {
+        let words = |line: &str| split_shell_words(line, true).unwrap();
+
+        assert_eq!(
+            words(r#""/opt/My Terminal/bin/term" --title 'akaw shell' -x"#),
+            ["/opt/My Terminal/bin/term", "--title", "akaw shell", "-x"]
+        );
+        assert_eq!(
+            words(r#"/opt/My\ Terminal/term "a \"b\"" ''"#),
+            ["/opt/My Terminal/term", r#"a "b""#, ""]
+        );
+        assert_eq!(
+            split_shell_words(r#""C:\Program Files\Term\term.exe" -e"#, false).unwrap(),
+            [r"C:\Program Files\Term\term.exe", "-e"]
+        );
+        assert!(split_shell_words("term 'open", true).is_err());
+        assert!(split_shell_words("  ", true).is_err());
     }
 }
@@ -1,108 +1,275 @@
-use super::{utils::get_aws_sessions_path, aws_config::AwsProfile};
+use super::{
+    aws_config::{AwsConfig, AwsProfile},
+    ini_document::IniDocument,
+    sessions_file::{SessionDefinition, SessionsFile},
+    utils::{get_aws_sessions_path, get_legacy_sessions_path},
+};
 use crate::{error::{AppError, Result}};
-use ini::Ini;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-const SESSION_CONFIG_FILE: &str = "sessions";
+pub const FORWARD_SEPARATOR: char = '.';
+
+pub const DEFAULT_DOCUMENT: &str = "AWS-StartPortForwardingSession";
+pub const REMOTE_HOST_DOCUMENT: &str = "AWS-StartPortForwardingSessionToRemoteHost";
 
 #[derive(Debug, Clone)]
 pub struct SessionConfig {
+    pub forward: Option<String>,
     pub target: String,
     pub local_port: u16,
     pub remote_port: u16,
     pub document_name: String,
+    pub host: Option<String>,
+    pub auto_reconnect: bool,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Values passed by the caller that take precedence over the sessions file.
+#[derive(Debug, Clone, Default)]
+pub struct SessionOverrides {
+    pub target: Option<String>,
+    pub local_port: Option<u16>,
+    pub remote_port: Option<u16>,
+    pub document_name: Option<String>,
+    pub host: Option<String>,
+    pub auto_reconnect: Option<bool>,
+}
+
+impl SessionOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.target.is_none()
+            && self.local_port.is_none()
+            && self.remote_port.is_none()
+            && self.document_name.is_none()
+            && self.host.is_none()
+            && self.auto_reconnect.is_none()
+    }
 }
 
 impl Default for SessionConfig {
     fn default() -> Self {
         Self {
+            forward: None,
             target: String::new(),
             local_port: 13389,
             remote_port: 3389,
-            document_name: "AWS-StartPortForwardingSession".to_string(),
+            document_name: DEFAULT_DOCUMENT.to_string(),
+            host: None,
+            auto_reconnect: false,
+            description: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl SessionConfig {
+    fn from_definition(forward: Option<&str>, target: String, definition: &SessionDefinition) -> Self {
+        let defaults = Self::default();
+
+        Self {
+            forward: forward.map(|f| f.to_string()),
+            target,
+            local_port: definition.local_port.unwrap_or(defaults.local_port),
+            remote_port: definition.remote_port.unwrap_or(defaults.remote_port),
+            document_name: definition
+                .document_name
+                .clone()
+                .unwrap_or(defaults.document_name),
+            host: definition.host.clone(),
+            auto_reconnect: definition.auto_reconnect.unwrap_or(defaults.auto_reconnect),
+            description: definition.description.clone(),
+            tags: definition.tags.clone(),
         }
     }
 }
 
+/// The sessions as found on disk.
+pub enum StoredSessions {
+    /// The versioned file, with its content for line lookups.
+    Current { path: PathBuf, content: String },
+    /// An ini file of earlier versions, not migrated yet.
+    Legacy { path: PathBuf, document: IniDocument },
+    Missing,
+}
+
 pub struct SessionConfigManager;
 
 impl SessionConfigManager {
-    pub fn load_config(profile: &str) -> Result<Option<SessionConfig>> {
+    /// Section holding a session config: `[profile]` for the default one,
+    /// `[profile.forward]` for a named forward.
+    pub fn section_name(profile: &str, forward: Option<&str>) -> String {
+        match forward {
+            Some(name) => format!("{}{}{}", profile, FORWARD_SEPARATOR, name),
+            None => profile.to_string(),
+        }
+    }
+
+    /// Names of the forwards defined for a profile among the given sections.
+    pub fn forward_names(sections: &[String], profile: &str) -> Vec<String> {
+        let prefix = format!("{}{}", profile, FORWARD_SEPARATOR);
+
+        sections
+            .iter()
+            .filter_map(|section| section.strip_prefix(&prefix))
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// Whether a profile has a default session config or any named forward.
+    pub fn is_configured(sections: &[String], profile: &str) -> bool {
+        sections.iter().any(|section| section == profile)
+            || !Self::forward_names(sections, profile).is_empty()
+    }
+
+    pub fn list_forwards(profile: &str) -> Result<Vec<String>> {
+        let file = Self::load_file()?;
+
+        Ok(file
+            .profiles
+            .get(profile)
+            .map(|definition| definition.forwards.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    /// Where the sessions are stored. `AKAW_SESSIONS_FILE` may point at an ini file as well.
+    pub fn stored() -> Result<StoredSessions> {
         let config_path = get_aws_sessions_path()?;
+        if config_path.exists() {
+            let content = fs::read_to_string(&config_path)?;
+            if !SessionsFile::is_ini(&content) {
+                return Ok(StoredSessions::Current {
+                    path: config_path,
+                    content,
+                });
+            }
+            return Ok(StoredSessions::Legacy {
+                document: IniDocument::parse(&content),
+                path: config_path,
+            });
+        }
 
-        if !config_path.exists() {
-            return Ok(None);
+        let legacy_path = get_legacy_sessions_path()?;
+        if legacy_path.exists() {
+            return Ok(StoredSessions::Legacy {
+                document: IniDocument::load(&legacy_path)?,
+                path: legacy_path,
+            });
         }
 
-        let ini = Ini::load_from_file(&config_path)
-            .map_err(|e| AppError::Custom(format!("Failed to read sessions: {}", e)))?;
+        Ok(StoredSessions::Missing)
+    }
 
-        let section = ini.section(Some(profile));
+    /// The sessions file. An ini file is converted in memory only, it is written in the
+    /// versioned format by [`Self::migrate`].
+    fn load_file() -> Result<SessionsFile> {
+        match Self::stored()? {
+            StoredSessions::Current { path, content } => {
+                SessionsFile::parse(&content, &path.display().to_string())
+            }
+            StoredSessions::Legacy { path, document } => Self::convert(&path, &document),
+            StoredSessions::Missing => Ok(SessionsFile::default()),
+        }
+    }
+
+    /// The sessions file, about to be changed. An ini file is migrated first, so the change
+    /// does not leave it behind, shadowed by the new file.
+    fn load_file_for_update() -> Result<SessionsFile> {
+        Self::migrate()?;
+        Self::load_file()
+    }
 
-        if section.is_none() {
+    /// Convert an ini sessions file, splitting dotted sections with the known profile names.
+    fn convert(path: &Path, document: &IniDocument) -> Result<SessionsFile> {
+        let profiles: Vec<String> = AwsConfig::list_profiles()
+            .map(|profiles| profiles.into_iter().map(|p| p.name).collect())
+            .unwrap_or_default();
+
+        SessionsFile::from_ini(document, &path.display().to_string(), &profiles)
+    }
+
+    /// Write an ini sessions file in the versioned format. The original is kept next to it,
+    /// under a name no earlier backup holds. Returns the backup, or `None` when there was
+    /// nothing to migrate.
+    pub fn migrate() -> Result<Option<PathBuf>> {
+        let StoredSessions::Legacy { path, document } = Self::stored()? else {
             return Ok(None);
+        };
+        let config_path = get_aws_sessions_path()?;
+        let file = Self::convert(&path, &document)?;
+
+        let backup_path = backup_path(&path);
+        fs::rename(&path, &backup_path)?;
+        if let Err(e) = file.save(&config_path) {
+            let _ = fs::rename(&backup_path, &path);
+            return Err(e);
         }
 
-        let section = section.unwrap();
+        log::info!(
+            "Migrated {} to {}, the original was kept as {}",
+            path.display(),
+            config_path.display(),
+            backup_path.display()
+        );
 
-        let target = section
-            .get("target")
-            .filter(|s| !s.trim().is_empty())
-            .ok_or_else(|| {
-                AppError::Custom(format!("No target defined for profile '{}'", profile))
-            })?
-            .to_string();
+        Ok(Some(backup_path))
+    }
 
-        let config = SessionConfig {
-            target,
-            local_port: section
-                .get("local_port")
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(13389),
-            remote_port: section
-                .get("remote_port")
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(3389),
-            document_name: section
-                .get("document_name")
-                .unwrap_or("AWS-StartPortForwardingSession")
-                .to_string(),
+    fn exists() -> Result<bool> {
+        Ok(!matches!(Self::stored()?, StoredSessions::Missing))
+    }
+
+    pub fn load_config(profile: &str, forward: Option<&str>) -> Result<Option<SessionConfig>> {
+        let file = Self::load_file()?;
+
+        let definition = match (file.profiles.get(profile), forward) {
+            (Some(definition), Some(name)) => definition.forwards.get(name),
+            (definition, None) => definition,
+            (None, Some(_)) => None,
         };
 
-        Ok(Some(config))
+        // A profile without a target only groups its forwards
+        Ok(definition.and_then(|definition| {
+            definition.target.clone().map(|target| {
+                SessionConfig::from_definition(forward, target, definition)
+            })
+        }))
     }
 
     pub fn create_config_from_profiles(profiles: Vec<AwsProfile>) -> Result<()> {
-        let config_path = get_aws_sessions_path()?;
-
-        if config_path.exists() {
+        if Self::exists()? {
             return Err(AppError::Custom(
                 "Configuration file already exists".to_string(),
             ));
         }
 
-        let mut ini = Ini::new();
+        let mut file = SessionsFile::default();
 
         for profile in profiles {
-            Self::init_session_section(&mut ini, &profile.name);
+            file.profiles.insert(profile.name, SessionDefinition::default());
         }
 
-        ini.write_to_file(&config_path)?;
-        Ok(())
+        file.save(&get_aws_sessions_path()?)
     }
 
+    /// Names of the configured sessions: `profile` for each profile and `profile.forward`
+    /// for each named forward.
     pub fn list_configured_profiles() -> Result<Vec<String>> {
-        let config_path = get_aws_sessions_path()?;
-
-        if !config_path.exists() {
-            return Ok(Vec::new());
-        }
+        let file = Self::load_file()?;
 
-        let ini = Ini::load_from_file(&config_path)
-            .map_err(|e| AppError::Custom(format!("Failed to read configuration file: {}", e)))?;
-
-        let profiles: Vec<String> = ini
+        let profiles: Vec<String> = file
+            .profiles
             .iter()
-            .filter_map(|(section, _)| section.map(|s| s.to_string()))
+            .flat_map(|(profile, definition)| {
+                std::iter::once(profile.clone()).chain(
+                    definition
+                        .forwards
+                        .keys()
+                        .map(|forward| Self::section_name(profile, Some(forward))),
+                )
+            })
             .collect();
 
         Ok(profiles)
@@ -110,106 +277,174 @@ impl SessionConfigManager {
 
     pub fn resolve_config(
         profile: &str,
-        cli_target: Option<String>,
-        cli_local_port: Option<u16>,
-        cli_remote_port: Option<u16>,
-        cli_document: Option<String>,
+        forward: Option<&str>,
+        overrides: SessionOverrides,
     ) -> Result<SessionConfig> {
-        let file_config = Self::load_config(profile)?;
+        let file_config = Self::load_config(profile, forward)?;
+        let sessions_file = get_aws_sessions_path()?.display().to_string();
+
+        if forward.is_some() && file_config.is_none() {
+            return Err(AppError::Custom(format!(
+                "Forward '{}' not found in {}",
+                Self::section_name(profile, forward),
+                sessions_file
+            )));
+        }
 
-        let target = cli_target
+        let target = overrides
+            .target
             .or_else(|| file_config.as_ref().map(|c| c.target.clone()))
             .ok_or_else(|| {
                 AppError::Custom(format!(
                     "No target specified. Add to {} or use --target",
-                    SESSION_CONFIG_FILE
+                    sessions_file
                 ))
             })?;
 
-        let local_port = cli_local_port
+        let local_port = overrides
+            .local_port
             .or_else(|| file_config.as_ref().map(|c| c.local_port))
             .unwrap_or(13389);
 
-        let remote_port = cli_remote_port
+        let remote_port = overrides
+            .remote_port
             .or_else(|| file_config.as_ref().map(|c| c.remote_port))
             .unwrap_or(3389);
 
-        let document_name = cli_document
+        let document_name = overrides
+            .document_name
             .or_else(|| file_config.as_ref().map(|c| c.document_name.clone()))
-            .unwrap_or_else(|| "AWS-StartPortForwardingSession".to_string());
+            .unwrap_or_else(|| DEFAULT_DOCUMENT.to_string());
+
+        let host = overrides
+            .host
+            .or_else(|| file_config.as_ref().and_then(|c| c.host.clone()))
+            .filter(|h| !h.trim().is_empty());
+
+        let auto_reconnect = overrides
+            .auto_reconnect
+            .or_else(|| file_config.as_ref().map(|c| c.auto_reconnect))
+            .unwrap_or(false);
+
+        if document_name == REMOTE_HOST_DOCUMENT && host.is_none() {
+            return Err(AppError::Custom(format!(
+                "A host is required for '{}'. Add it to {} or use --host",
+                REMOTE_HOST_DOCUMENT, sessions_file
+            )));
+        }
+
+        if host.is_some() && document_name != REMOTE_HOST_DOCUMENT {
+            return Err(AppError::Custom(format!(
+                "A host is only supported by '{}', not '{}'. Remove it or change document_name",
+                REMOTE_HOST_DOCUMENT, document_name
+            )));
+        }
 
         Ok(SessionConfig {
+            forward: forward.map(|f| f.to_string()),
             target,
             local_port,
             remote_port,
             document_name,
+            host,
+            auto_reconnect,
+            description: file_config.as_ref().and_then(|c| c.description.clone()),
+            tags: file_config.map(|c| c.tags).unwrap_or_default(),
         })
     }
 
-    fn init_session_section(ini: &mut Ini, name: &str) {
-        ini.with_section(Some(name))
-            .set("target", "")
-            .set("local_port", "")
-            .set("remote_port", "")
-            .set("document_name", "");
-    }
-
     pub fn update_config(
         profile: &str,
-        target: Option<String>,
-        local_port: Option<u16>,
-        remote_port: Option<u16>,
-        document_name: Option<String>,
+        forward: Option<&str>,
+        overrides: SessionOverrides,
     ) -> Result<()> {
         let config_path = get_aws_sessions_path()?;
+        let mut file = Self::load_file_for_update()?;
 
-        let mut ini = if config_path.exists() {
-            Ini::load_from_file(&config_path)
-                .map_err(|e| AppError::Custom(format!("Failed to read sessions: {}", e)))?
-        } else {
-            Ini::new()
+        let entry = file.profiles.entry(profile.to_string()).or_default();
+        let definition = match forward {
+            Some(name) => entry.forwards.entry(name.to_string()).or_default(),
+            None => entry,
         };
 
-        // Ensure section exists (or created if new)
-        // We set values directly.
+        if let Some(val) = overrides.target {
+            definition.target = Some(val);
+        }
+
+        if let Some(val) = overrides.local_port {
+            definition.local_port = Some(val);
+        }
 
-        if let Some(val) = target {
-            ini.set_to(Some(profile), "target".to_string(), val);
+        if let Some(val) = overrides.remote_port {
+            definition.remote_port = Some(val);
         }
 
-        if let Some(val) = local_port {
-            ini.set_to(Some(profile), "local_port".to_string(), val.to_string());
+        if let Some(val) = overrides.document_name {
+            definition.document_name = Some(val);
         }
 
-        if let Some(val) = remote_port {
-            ini.set_to(Some(profile), "remote_port".to_string(), val.to_string());
+        if let Some(val) = overrides.host {
+            definition.host = Some(val);
         }
 
-        if let Some(val) = document_name {
-            ini.set_to(Some(profile), "document_name".to_string(), val);
+        if let Some(val) = overrides.auto_reconnect {
+            definition.auto_reconnect = Some(val);
         }
 
-        ini.write_to_file(&config_path)
+        file.save(&config_path)
             .map_err(|e| AppError::Custom(format!("Failed to write config: {}", e)))?;
 
         Ok(())
     }
 
     pub fn remove_config(profile: &str) -> Result<()> {
-        let config_path = get_aws_sessions_path()?;
-
-        if !config_path.exists() {
+        if !Self::exists()? {
             return Ok(());
         }
 
-        let mut ini = Ini::load_from_file(&config_path)
-            .map_err(|e| AppError::Custom(format!("Failed to read sessions: {}", e)))?;
+        let mut file = Self::load_file_for_update()?;
 
-        ini.delete(Some(profile));
-
-        ini.write_to_file(&config_path)
-            .map_err(|e| AppError::Custom(format!("Failed to write config: {}", e)))?;
+        if file.profiles.remove(profile).is_some() {
+            file.save(&get_aws_sessions_path()?)
+                .map_err(|e| AppError::Custom(format!("Failed to write config: {}", e)))?;
+        }
 
         Ok(())
     }
 }
+
+/// `<file>.bak`, or the first of `<file>.bak.1`, `<file>.bak.2`, ... that does not exist yet.
+fn backup_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let candidate = |n: u32| match n {
+        0 => path.with_file_name(format!("{}.bak", name)),
+        n => path.with_file_name(format!("{}.bak.{}", name, n)),
+    };
+
+    (0..)
+        .map(candidate)
+        .find(|backup| !backup.exists())
+        .unwrap_or_else(|| candidate(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_reuses_a_backup_name() {
+        let dir = std::env::temp_dir().join(format!("akaw-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sessions");
+
+        assert_eq!(backup_path(&path), dir.join("sessions.bak"));
+        fs::write(dir.join("sessions.bak"), "").unwrap();
+        fs::write(dir.join("sessions.bak.1"), "").unwrap();
+        assert_eq!(backup_path(&path), dir.join("sessions.bak.2"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use super::settings::AwsSettings;
+use crate::util::dirs::get_user_session_logs_dir;
+use crate::util::formatter::log_time_fmt;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+use time::OffsetDateTime;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::Child;
+
+pub const EVENT_SESSION_LOG: &str = "session-log";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    pub timestamp: String,
+    pub stream: LogStream,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionLogEvent {
+    pub id: u64,
+    pub line: LogLine,
+}
+
+/// Bounded buffer holding the output of the session-manager-plugin for one session.
+#[derive(Debug, Clone)]
+pub struct SessionLog {
+    id: u64,
+    capacity: usize,
+    /// The lines with the run of the child that wrote them.
+    lines: Arc<Mutex<VecDeque<(u64, LogLine)>>>,
+    /// Counts the children attached, so the output of the current one can be told apart
+    /// from that of earlier runs.
+    run: Arc<AtomicU64>,
+    file: Option<PathBuf>,
+}
+
+impl SessionLog {
+    pub fn new(id: u64, profile: &str) -> Self {
+        let settings = AwsSettings::load();
+
+        let file = if settings.session_log_files {
+            let dir = get_user_session_logs_dir();
+            let _ = std::fs::create_dir_all(&dir);
+            let started = OffsetDateTime::now_utc().unix_timestamp();
+            Some(dir.join(format!("{}-{}-{}.log", profile, started, id)))
+        } else {
+            None
+        };
+
+        Self {
+            id,
+            capacity: settings.session_log_lines.max(1),
+            lines: Arc::new(Mutex::new(VecDeque::new())),
+            run: Arc::new(AtomicU64::new(0)),
+            file,
+        }
+    }
+
+    /// Take the piped stdout and stderr of a child and forward every line into this log.
+    /// The child becomes the current run.
+    pub fn attach(&self, app: &AppHandle, child: &mut Child) {
+        let run = self.run.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if let Some(stdout) = child.stdout.take() {
+            self.follow(app.clone(), stdout, LogStream::Stdout, run);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            self.follow(app.clone(), stderr, LogStream::Stderr, run);
+        }
+    }
+
+    /// Return the buffered lines, limited to the last `tail` ones when given.
+    pub fn lines(&self, tail: Option<usize>) -> Vec<LogLine> {
+        let lines = self.lines.lock().unwrap();
+        let skip = tail.map_or(0, |n| lines.len().saturating_sub(n));
+        lines
+            .iter()
+            .skip(skip)
+            .map(|(_, line)| line.clone())
+            .collect()
+    }
+
+    /// Whether the current run wrote a line containing `pattern`.
+    pub fn contains(&self, pattern: &str) -> bool {
+        self.current_run()
+            .iter()
+            .any(|line| line.text.contains(pattern))
+    }
+
+    /// Join the lines of the current run, optionally keeping only one stream.
+    pub fn text(&self, stream: Option<LogStream>) -> String {
+        self.current_run()
+            .iter()
+            .filter(|line| stream.is_none_or(|s| s == line.stream))
+            .map(|line| line.text.trim())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn current_run(&self) -> Vec<LogLine> {
+        let run = self.run.load(Ordering::SeqCst);
+        let lines = self.lines.lock().unwrap();
+        lines
+            .iter()
+            .filter(|(line_run, _)| *line_run == run)
+            .map(|(_, line)| line.clone())
+            .collect()
+    }
+
+    fn push(&self, run: u64, stream: LogStream, text: &str) -> LogLine {
+        let line = LogLine {
+            timestamp: OffsetDateTime::now_utc().format(log_time_fmt()).unwrap(),
+            stream,
+            text: text.to_string(),
+        };
+
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() >= self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back((run, line.clone()));
+
+        line
+    }
+
+    fn follow<R>(&self, app: AppHandle, reader: R, stream: LogStream, run: u64)
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let log = self.clone();
+
+        tauri::async_runtime::spawn(async move {
+            let mut file = match &log.file {
+                Some(path) => OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .ok(),
+                None => None,
+            };
+
+            let mut reader = BufReader::new(reader).lines();
+
+            while let Ok(Some(text)) = reader.next_line().await {
+                let line = log.push(run, stream, &text);
+
+                if let Some(file) = file.as_mut() {
+                    let entry = format!("{} [{:?}] {}\n", line.timestamp, line.stream, line.text);
+                    let _ = file.write_all(entry.as_bytes()).await;
+                }
+
+                let _ = app.emit(EVENT_SESSION_LOG, SessionLogEvent { id: log.id, line });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::fake_backend::use_offline_environment;
+
+    #[test]
+    fn reads_only_the_current_run() {
+        use_offline_environment();
+        let log = SessionLog::new(1, "dev");
+
+        log.run.store(1, Ordering::SeqCst);
+        log.push(1, LogStream::Stderr, "An error occurred (ExpiredToken)");
+        log.run.store(2, Ordering::SeqCst);
+        log.push(2, LogStream::Stdout, "Waiting for connections...");
+        log.push(2, LogStream::Stderr, "Cannot perform start session: EOF");
+        // A late line of the previous child
+        log.push(1, LogStream::Stderr, "ExpiredToken");
+
+        assert_eq!(
+            log.text(Some(LogStream::Stderr)),
+            "Cannot perform start session: EOF"
+        );
+        assert!(log.contains("Waiting for connections"));
+        assert!(!log.contains("ExpiredToken"));
+        assert_eq!(log.lines(None).len(), 4);
+    }
+}
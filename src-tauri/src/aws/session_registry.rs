@@ -0,0 +1,290 @@
+use super::models::SessionCredentials;
+use super::pid_file::PidFile;
+use super::session::SessionManager;
+use super::session_config::SessionConfig;
+use super::session_log::SessionLog;
+use crate::error::{AppError, Result};
+use crate::util::process::{is_tree_running, kill_tree};
+use std::collections::HashMap;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tauri::AppHandle;
+use time::OffsetDateTime;
+use tokio::process::Child;
+use tokio::sync::Mutex;
+
+/// How long a restart waits for the old process tree to release the local port.
+const RELEASE_INTERVAL: Duration = Duration::from_millis(250);
+const RELEASE_ATTEMPTS: u32 = 40;
+
+#[derive(Debug, Clone)]
+pub struct ActiveSession {
+    pub id: u64,
+    pub profile: String,
+    pub config: SessionConfig,
+    pub pid: Option<u32>,
+    pub started_at: OffsetDateTime,
+    pub expiration: OffsetDateTime,
+    pub reconnects: u32,
+}
+
+#[derive(Debug)]
+pub enum ChildState {
+    Running,
+    Exited(Option<ExitStatus>),
+}
+
+struct TrackedSession {
+    session: ActiveSession,
+    child: Child,
+    log: SessionLog,
+    /// Set while `restart` swaps the child, so the supervisor does not reconnect it as well.
+    restarting: bool,
+}
+
+/// Keeps every `aws ssm start-session` child spawned by the app, keyed by session id.
+#[derive(Default)]
+pub struct SessionRegistry {
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<u64, TrackedSession>>,
+}
+
+impl SessionRegistry {
+    /// Track a freshly spawned child and start capturing its output.
+    pub async fn register(
+        &self,
+        app: &AppHandle,
+        profile: &str,
+        config: &SessionConfig,
+        expiration: OffsetDateTime,
+        mut child: Child,
+    ) -> ActiveSession {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let log = SessionLog::new(id, profile);
+        log.attach(app, &mut child);
+
+        let session = ActiveSession {
+            id,
+            profile: profile.to_string(),
+            config: config.clone(),
+            pid: child.id(),
+            started_at: OffsetDateTime::now_utc(),
+            expiration,
+            reconnects: 0,
+        };
+
+        let mut sessions = self.sessions.lock().await;
+        sessions.insert(
+            id,
+            TrackedSession {
+                session: session.clone(),
+                child,
+                log,
+                restarting: false,
+            },
+        );
+        Self::persist(&sessions);
+
+        session
+    }
+
+    /// List running sessions, dropping the ones whose process has already exited.
+    /// Supervised sessions are kept while their supervisor is reconnecting them.
+    pub async fn list(&self) -> Vec<ActiveSession> {
+        let mut sessions = self.sessions.lock().await;
+
+        sessions.retain(|_, tracked| {
+            tracked.session.config.auto_reconnect || matches!(tracked.child.try_wait(), Ok(None))
+        });
+        Self::persist(&sessions);
+
+        let mut active: Vec<ActiveSession> = sessions.values().map(|t| t.session.clone()).collect();
+        active.sort_by_key(|s| s.id);
+        active
+    }
+
+    /// Check whether the child of a session is still running.
+    /// Returns `None` once the session is no longer tracked.
+    pub async fn poll(&self, id: u64) -> Option<ChildState> {
+        let mut sessions = self.sessions.lock().await;
+        let tracked = sessions.get_mut(&id)?;
+
+        match tracked.child.try_wait() {
+            Ok(None) => Some(ChildState::Running),
+            _ if tracked.restarting => Some(ChildState::Running),
+            Ok(status) => Some(ChildState::Exited(status)),
+            Err(_) => Some(ChildState::Exited(None)),
+        }
+    }
+
+    pub async fn get(&self, id: u64) -> Option<ActiveSession> {
+        self.sessions
+            .lock()
+            .await
+            .get(&id)
+            .map(|tracked| tracked.session.clone())
+    }
+
+    pub async fn log(&self, id: u64) -> Result<SessionLog> {
+        self.sessions
+            .lock()
+            .await
+            .get(&id)
+            .map(|tracked| tracked.log.clone())
+            .ok_or(AppError::SessionNotFound(id))
+    }
+
+    /// Swap in a freshly spawned child for an existing session.
+    /// The child is handed back if the session was stopped in the meantime.
+    pub async fn replace_child(
+        &self,
+        app: &AppHandle,
+        id: u64,
+        mut child: Child,
+        expiration: OffsetDateTime,
+    ) -> std::result::Result<ActiveSession, Child> {
+        let mut sessions = self.sessions.lock().await;
+
+        let Some(tracked) = sessions.get_mut(&id) else {
+            return Err(child);
+        };
+
+        tracked.log.attach(app, &mut child);
+
+        tracked.session.pid = child.id();
+        tracked.session.expiration = expiration;
+        tracked.session.reconnects += 1;
+        tracked.child = child;
+
+        let session = tracked.session.clone();
+        Self::persist(&sessions);
+
+        Ok(session)
+    }
+
+    /// Restart the child of a session with new credentials, keeping its id and log.
+    /// The old process tree is stopped outside the lock, and the new child is only started
+    /// once the session-manager-plugin has let go of the local port.
+    pub async fn restart(
+        &self,
+        app: &AppHandle,
+        id: u64,
+        credentials: &SessionCredentials,
+    ) -> Result<ActiveSession> {
+        let session = {
+            let mut sessions = self.sessions.lock().await;
+            let tracked = sessions.get_mut(&id).ok_or(AppError::SessionNotFound(id))?;
+            tracked.restarting = true;
+            tracked.session.clone()
+        };
+
+        if let Some(pid) = session.pid {
+            let _ = tokio::task::spawn_blocking(move || kill_tree(pid)).await;
+        }
+
+        let started = async {
+            self.wait_for_release(id, session.pid, session.config.local_port)
+                .await?;
+            SessionManager::start_session(&session.profile, &session.config, credentials).await
+        }
+        .await;
+
+        let mut sessions = self.sessions.lock().await;
+        let Some(tracked) = sessions.get_mut(&id) else {
+            // Stopped while restarting
+            drop(sessions);
+            if let Ok(mut child) = started {
+                Self::terminate(&mut child).await?;
+            }
+            return Err(AppError::SessionNotFound(id));
+        };
+        tracked.restarting = false;
+        let mut child = started?;
+
+        tracked.log.attach(app, &mut child);
+
+        tracked.session.pid = child.id();
+        tracked.session.expiration = credentials.expiration;
+        tracked.child = child;
+
+        let session = tracked.session.clone();
+        Self::persist(&sessions);
+
+        Ok(session)
+    }
+
+    /// Wait until the process group of a stopped child is gone and its local port is free.
+    async fn wait_for_release(&self, id: u64, pid: Option<u32>, port: u16) -> Result<()> {
+        for _ in 0..RELEASE_ATTEMPTS {
+            // Reap the child, which would otherwise keep its process group alive as a zombie
+            self.poll(id).await;
+
+            let exited = pid.is_none_or(|pid| !is_tree_running(pid));
+            if exited && !SessionManager::is_port_in_use(port) {
+                return Ok(());
+            }
+            tokio::time::sleep(RELEASE_INTERVAL).await;
+        }
+
+        Err(AppError::PortInUse(port))
+    }
+
+    pub async fn stop(&self, id: u64) -> Result<ActiveSession> {
+        let tracked = {
+            let mut sessions = self.sessions.lock().await;
+            let tracked = sessions.remove(&id).ok_or(AppError::SessionNotFound(id))?;
+            Self::persist(&sessions);
+            tracked
+        };
+
+        Self::kill(tracked).await
+    }
+
+    pub async fn stop_all(&self) -> Vec<ActiveSession> {
+        let drained: Vec<TrackedSession> = self
+            .sessions
+            .lock()
+            .await
+            .drain()
+            .map(|(_, tracked)| tracked)
+            .collect();
+
+        PidFile::save(&[]);
+
+        let mut stopped = Vec::new();
+
+        for tracked in drained {
+            if let Ok(session) = Self::kill(tracked).await {
+                stopped.push(session);
+            }
+        }
+
+        stopped
+    }
+
+    async fn kill(mut tracked: TrackedSession) -> Result<ActiveSession> {
+        Self::terminate(&mut tracked.child).await?;
+        Ok(tracked.session)
+    }
+
+    /// Kill a running child along with the session-manager-plugin it spawned.
+    pub async fn terminate(child: &mut Child) -> Result<()> {
+        if let Ok(None) = child.try_wait() {
+            if let Some(pid) = child.id() {
+                let _ = tokio::task::spawn_blocking(move || kill_tree(pid)).await;
+            }
+            if let Ok(None) = child.try_wait() {
+                child.kill().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn persist(sessions: &HashMap<u64, TrackedSession>) {
+        let pids: Vec<u32> = sessions.values().filter_map(|t| t.session.pid).collect();
+        PidFile::save(&pids);
+    }
+}
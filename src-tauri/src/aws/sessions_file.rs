@@ -0,0 +1,664 @@
+use super::ini_document::{IniDocument, IniEntry};
+use super::session_config::{FORWARD_SEPARATOR, REMOTE_HOST_DOCUMENT};
+use crate::error::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Version written to new files. Files with a newer version are refused rather than misread.
+pub const SESSIONS_VERSION: u32 = 1;
+
+/// Where a problem was found, as the keys leading to it, and what is wrong.
+pub type Problem = (Vec<String>, String);
+
+/// The sessions file: the session of each profile and its named forwards.
+/// Published as `schemas/sessions.schema.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionsFile {
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    pub version: u32,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, SessionDefinition>,
+}
+
+/// A session as written in the file. Unset ports and document fall back to the defaults
+/// when the session is resolved.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionDefinition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_reconnect: Option<bool>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub forwards: BTreeMap<String, SessionDefinition>,
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: Option<u32>,
+}
+
+/// A value left out when reading a file leniently, for a linter to report.
+#[derive(Debug, Clone)]
+pub struct InvalidValue {
+    /// The session, as `profile` or `profile.forward`.
+    pub session: String,
+    pub key: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Default for SessionsFile {
+    fn default() -> Self {
+        Self {
+            schema: None,
+            version: SESSIONS_VERSION,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl SessionsFile {
+    /// Parse and validate a file. Errors point at the line of the offending value.
+    pub fn parse(content: &str, source: &str) -> Result<Self> {
+        let content = without_bom(content);
+        let file = Self::parse_unchecked(content, source)?;
+
+        if let Some((keys, message)) = file.problems().into_iter().next() {
+            return Err(AppError::InvalidConfig(
+                source.to_string(),
+                locate(content, &keys),
+                message,
+            ));
+        }
+
+        Ok(file)
+    }
+
+    /// Parse a file that is well-formed and typed correctly, leaving the checks of
+    /// [`Self::problems`] to the caller.
+    pub fn parse_unchecked(content: &str, source: &str) -> Result<Self> {
+        let content = without_bom(content);
+        Self::check_version(content, source)?;
+        serde_json::from_str(content).map_err(|e| syntax_error(source, e))
+    }
+
+    /// Parse a file like [`Self::parse_unchecked`], but drop every port that does not fit
+    /// instead of failing on the first, so all of them can be reported with the other problems.
+    pub fn parse_lenient(content: &str, source: &str) -> Result<(Self, Vec<InvalidValue>)> {
+        let content = without_bom(content);
+        Self::check_version(content, source)?;
+        let mut value: Value =
+            serde_json::from_str(content).map_err(|e| syntax_error(source, e))?;
+        let mut invalid = Vec::new();
+
+        if let Some(profiles) = value.get_mut("profiles").and_then(Value::as_object_mut) {
+            for (profile, definition) in profiles.iter_mut() {
+                let keys = vec!["profiles".to_string(), profile.clone()];
+                drop_invalid_ports(definition, profile, &keys, content, &mut invalid);
+
+                let Some(forwards) = definition
+                    .get_mut("forwards")
+                    .and_then(Value::as_object_mut)
+                else {
+                    continue;
+                };
+                for (forward, definition) in forwards.iter_mut() {
+                    let keys =
+                        [keys.clone(), vec!["forwards".to_string(), forward.clone()]].concat();
+                    let session = format!("{}{}{}", profile, FORWARD_SEPARATOR, forward);
+                    drop_invalid_ports(definition, &session, &keys, content, &mut invalid);
+                }
+            }
+        }
+
+        match serde_json::from_value(value) {
+            Ok(file) => Ok((file, invalid)),
+            // Read the text again for an error that carries the line
+            Err(e) => Err(Self::parse_unchecked(content, source)
+                .err()
+                .unwrap_or_else(|| AppError::InvalidConfig(source.to_string(), 1, e.to_string()))),
+        }
+    }
+
+    /// Read before the full parse, so a newer file is reported as such instead of as unknown
+    /// fields.
+    fn check_version(content: &str, source: &str) -> Result<()> {
+        let invalid = |keys: &[String], message: String| {
+            AppError::InvalidConfig(source.to_string(), locate(content, keys), message)
+        };
+
+        let probe: VersionProbe =
+            serde_json::from_str(content).map_err(|e| syntax_error(source, e))?;
+        let version_key = ["version".to_string()];
+        match probe.version {
+            None => Err(invalid(&[], "missing \"version\"".to_string())),
+            Some(0) => Err(invalid(&version_key, "unsupported version 0".to_string())),
+            Some(version) if version > SESSIONS_VERSION => Err(invalid(
+                &version_key,
+                format!(
+                    "version {} was written by a newer akaw, this one reads up to {}",
+                    version, SESSIONS_VERSION
+                ),
+            )),
+            Some(_) => Ok(()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some((keys, message)) = self.problems().into_iter().next() {
+            return Err(AppError::Custom(format!("{}: {}", keys.join("."), message)));
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// The problems the schema cannot express, such as an empty target.
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        for (profile, definition) in &self.profiles {
+            let keys = vec!["profiles".to_string(), profile.clone()];
+            if profile.trim().is_empty() {
+                problems.push((keys.clone(), "profile names must not be empty".to_string()));
+            }
+            problems.extend(definition.problem(&keys));
+
+            for (name, forward) in &definition.forwards {
+                let keys = [keys.clone(), vec!["forwards".to_string(), name.clone()]].concat();
+                if name.trim().is_empty() {
+                    problems.push((keys.clone(), "forward names must not be empty".to_string()));
+                }
+                if forward.target.is_none() {
+                    problems.push((keys.clone(), format!("forward '{}' has no target", name)));
+                }
+                if !forward.forwards.is_empty() {
+                    let keys = [keys.clone(), vec!["forwards".to_string()]].concat();
+                    problems.push((keys, "forwards cannot be nested".to_string()));
+                }
+                problems.extend(forward.problem(&keys));
+            }
+        }
+
+        problems
+    }
+
+    /// Whether the content is an ini file of earlier versions rather than the versioned format,
+    /// which is always a JSON object.
+    pub fn is_ini(content: &str) -> bool {
+        let content = without_bom(content).trim_start();
+        !content.is_empty() && !content.starts_with('{')
+    }
+
+    /// Convert the ini file of earlier versions, where `[profile]` holds the session of a
+    /// profile and `[profile.forward]` a named forward. Known profile names settle sections
+    /// whose profile name contains a dot itself. The comments of a section are kept in its
+    /// description, since the JSON format has no comments.
+    pub fn from_ini(document: &IniDocument, source: &str, profiles: &[String]) -> Result<Self> {
+        let (file, invalid) = Self::from_ini_lenient(document, source, profiles)?;
+
+        match invalid.into_iter().next() {
+            Some(value) => Err(AppError::InvalidConfig(
+                source.to_string(),
+                value.line,
+                value.message,
+            )),
+            None => Ok(file),
+        }
+    }
+
+    /// Convert an ini file like [`Self::from_ini`], but report the invalid values of every
+    /// section instead of failing on the first. Ports that do not parse are left out.
+    pub fn from_ini_lenient(
+        document: &IniDocument,
+        source: &str,
+        profiles: &[String],
+    ) -> Result<(Self, Vec<InvalidValue>)> {
+        let mut file = Self::default();
+        let mut invalid = Vec::new();
+
+        for (section, header_line, entries) in document.entries() {
+            let mut definition =
+                SessionDefinition::from_ini(&entries, source, &section, &mut invalid)?;
+
+            let notes: Vec<String> = definition
+                .description
+                .take()
+                .into_iter()
+                .chain(document.comments(&section).into_iter().map(comment_text))
+                .filter(|note| !note.is_empty())
+                .collect();
+            if !notes.is_empty() {
+                definition.description = Some(notes.join("\n"));
+            }
+
+            let mut report = |key: Option<&String>, message: String| {
+                let line = key
+                    .and_then(|key| entries.iter().find(|entry| &entry.key == key))
+                    .map_or(header_line, |entry| entry.line);
+                invalid.push(InvalidValue {
+                    session: section.clone(),
+                    key: key.cloned().unwrap_or_default(),
+                    line,
+                    message,
+                });
+            };
+            if let Some((keys, message)) = definition.problem(&[]) {
+                report(keys.last(), message);
+            }
+
+            let (profile, forward) = split_section(&section, profiles);
+            let entry = file.profiles.entry(profile).or_default();
+
+            match forward {
+                Some(name) => {
+                    if definition.target.is_none() {
+                        report(None, format!("forward '{}' has no target", name));
+                    }
+                    entry.forwards.insert(name, definition);
+                }
+                None => {
+                    let forwards = std::mem::take(&mut entry.forwards);
+                    *entry = SessionDefinition {
+                        forwards,
+                        ..definition
+                    };
+                }
+            }
+        }
+
+        Ok((file, invalid))
+    }
+}
+
+impl SessionDefinition {
+    fn problem(&self, keys: &[String]) -> Option<Problem> {
+        let at = |key: &str| [keys.to_vec(), vec![key.to_string()]].concat();
+
+        if self.target.as_ref().is_some_and(|t| t.trim().is_empty()) {
+            return Some((at("target"), "target must not be empty".to_string()));
+        }
+        for (key, port) in [
+            ("local_port", self.local_port),
+            ("remote_port", self.remote_port),
+        ] {
+            if port == Some(0) {
+                return Some((at(key), format!("{} must be between 1 and 65535", key)));
+            }
+        }
+        if self
+            .document_name
+            .as_ref()
+            .is_some_and(|d| d.trim().is_empty())
+        {
+            return Some((
+                at("document_name"),
+                "document_name must not be empty".to_string(),
+            ));
+        }
+        if self.document_name.as_deref() == Some(REMOTE_HOST_DOCUMENT)
+            && self.host.as_ref().is_none_or(|h| h.trim().is_empty())
+        {
+            return Some((
+                at("document_name"),
+                format!("a host is required for '{}'", REMOTE_HOST_DOCUMENT),
+            ));
+        }
+
+        None
+    }
+
+    /// Read the keys of a section. Ports that do not parse are reported to `invalid` and left
+    /// out, other bad values fail.
+    fn from_ini(
+        entries: &[IniEntry],
+        source: &str,
+        section: &str,
+        invalid: &mut Vec<InvalidValue>,
+    ) -> Result<Self> {
+        let mut definition = Self::default();
+
+        for entry in entries {
+            let value = entry.value.trim();
+            // Keys created empty by the initial configuration are placeholders
+            if value.is_empty() {
+                continue;
+            }
+
+            let message = |what: &str| format!("{} '{}' is not a valid {}", entry.key, value, what);
+            let mut port = || match value.parse() {
+                Ok(port) => Some(port),
+                Err(_) => {
+                    invalid.push(InvalidValue {
+                        session: section.to_string(),
+                        key: entry.key.clone(),
+                        line: entry.line,
+                        message: message("port"),
+                    });
+                    None
+                }
+            };
+
+            match entry.key.as_str() {
+                "target" => definition.target = Some(value.to_string()),
+                "local_port" => definition.local_port = port(),
+                "remote_port" => definition.remote_port = port(),
+                "document_name" => definition.document_name = Some(value.to_string()),
+                "host" => definition.host = Some(value.to_string()),
+                "auto_reconnect" => {
+                    definition.auto_reconnect = Some(value.parse().map_err(|_| {
+                        AppError::InvalidConfig(source.to_string(), entry.line, message("boolean"))
+                    })?)
+                }
+                "description" => definition.description = Some(value.to_string()),
+                "tags" => {
+                    definition.tags = value
+                        .split(',')
+                        .map(|tag| tag.trim().to_string())
+                        .filter(|tag| !tag.is_empty())
+                        .collect()
+                }
+                key => {
+                    return Err(AppError::InvalidConfig(
+                        source.to_string(),
+                        entry.line,
+                        format!("unknown key '{}'", key),
+                    ))
+                }
+            }
+        }
+
+        Ok(definition)
+    }
+}
+
+/// A JSON syntax or type error, with the line serde found it on.
+fn syntax_error(source: &str, e: serde_json::Error) -> AppError {
+    let text = e.to_string();
+    let message = text.split(" at line ").next().unwrap_or(&text);
+    AppError::InvalidConfig(
+        source.to_string(),
+        e.line(),
+        format!("{} (column {})", message, e.column()),
+    )
+}
+
+/// Remove the ports of a session that are not numbers a `u16` holds, reporting each.
+/// Port 0 is left to [`SessionsFile::problems`].
+fn drop_invalid_ports(
+    definition: &mut Value,
+    session: &str,
+    keys: &[String],
+    content: &str,
+    invalid: &mut Vec<InvalidValue>,
+) {
+    let Some(fields) = definition.as_object_mut() else {
+        return;
+    };
+
+    for key in ["local_port", "remote_port"] {
+        let Some(value) = fields.get(key) else {
+            continue;
+        };
+        if value.is_null() || value.as_u64().is_some_and(|port| port <= u16::MAX as u64) {
+            continue;
+        }
+
+        invalid.push(InvalidValue {
+            session: session.to_string(),
+            key: key.to_string(),
+            line: locate(content, &[keys.to_vec(), vec![key.to_string()]].concat()),
+            message: format!("{} {} is not a port between 1 and 65535", key, value),
+        });
+        fields.remove(key);
+    }
+}
+
+/// The text of a `#` or `;` comment line.
+fn comment_text(line: &str) -> String {
+    line.trim()
+        .trim_start_matches(['#', ';'])
+        .trim()
+        .to_string()
+}
+
+fn split_section(section: &str, profiles: &[String]) -> (String, Option<String>) {
+    if profiles.iter().any(|profile| profile == section) {
+        return (section.to_string(), None);
+    }
+
+    let known = profiles
+        .iter()
+        .filter(|profile| {
+            section
+                .strip_prefix(profile.as_str())
+                .is_some_and(|rest| rest.starts_with(FORWARD_SEPARATOR))
+        })
+        .max_by_key(|profile| profile.len());
+
+    match known {
+        Some(profile) => (
+            profile.clone(),
+            Some(section[profile.len() + 1..].to_string()),
+        ),
+        None => match section.split_once(FORWARD_SEPARATOR) {
+            Some((profile, forward)) => (profile.to_string(), Some(forward.to_string())),
+            None => (section.to_string(), None),
+        },
+    }
+}
+
+/// Line of the value reached through `keys`, or of the last of them that was found. The
+/// objects are walked, so a key only matches as a member name of the object before it and
+/// not where the same text appears in a value.
+pub fn locate(content: &str, keys: &[String]) -> usize {
+    let content = without_bom(content);
+    let mut line_at = 0;
+    let mut object = 0;
+
+    for key in keys {
+        match find_member(content, object, key) {
+            Some((key_end, value)) => {
+                line_at = key_end;
+                object = value;
+            }
+            None => break,
+        }
+    }
+
+    content[..line_at].matches('\n').count() + 1
+}
+
+/// The end of the name and the start of the value of a member of the object at `start`.
+fn find_member(content: &str, start: usize, key: &str) -> Option<(usize, usize)> {
+    let bytes = content.as_bytes();
+    let mut i = skip_whitespace(bytes, start);
+    if bytes.get(i) != Some(&b'{') {
+        return None;
+    }
+    i += 1;
+
+    loop {
+        i = skip_whitespace(bytes, i);
+        if bytes.get(i) != Some(&b'"') {
+            return None;
+        }
+        let key_end = skip_string(bytes, i)?;
+        let name: String = serde_json::from_str(&content[i..key_end]).ok()?;
+
+        i = skip_whitespace(bytes, key_end);
+        if bytes.get(i) != Some(&b':') {
+            return None;
+        }
+        let value = skip_whitespace(bytes, i + 1);
+        if name == key {
+            return Some((key_end, value));
+        }
+
+        i = skip_whitespace(bytes, skip_value(bytes, value)?);
+        match bytes.get(i) {
+            Some(b',') => i += 1,
+            _ => return None,
+        }
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+        i += 1;
+    }
+    i
+}
+
+/// The position after the string starting at `start`.
+fn skip_string(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    loop {
+        match bytes.get(i)? {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+}
+
+/// The position after the value starting at `start`.
+fn skip_value(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = start;
+
+    loop {
+        match *bytes.get(i)? {
+            b'"' => i = skip_string(bytes, i)?,
+            b'{' | b'[' => {
+                depth += 1;
+                i += 1;
+            }
+            b'}' | b']' if depth > 0 => {
+                depth -= 1;
+                i += 1;
+            }
+            b',' | b'}' | b']' if depth == 0 => return Some(i),
+            byte if depth == 0 && byte.is_ascii_whitespace() => return Some(i),
+            _ => i += 1,
+        }
+        if depth == 0 && i > start && matches!(bytes[i - 1], b'"' | b'}' | b']') {
+            return Some(i);
+        }
+    }
+}
+
+/// serde_json refuses the byte order mark some editors write.
+fn without_bom(content: &str) -> &str {
+    content.strip_prefix('\u{feff}').unwrap_or(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY: &str = "\
+# Sessions of the team
+[dev]
+target = i-0123456789abcdef0
+local_port = 13389
+
+; Database through the bastion
+[dev.db]
+target = i-0123456789abcdef0
+local_port = 15432
+remote_port = 5432
+description = Postgres
+# reachable from the VPN only
+";
+
+    #[test]
+    fn tells_ini_from_json() {
+        assert!(SessionsFile::is_ini(LEGACY));
+        assert!(SessionsFile::is_ini(
+            "[dev]\ntarget = i-0123456789abcdef0\n"
+        ));
+        assert!(!SessionsFile::is_ini("\u{feff}\n  {\"version\": 1}"));
+        assert!(!SessionsFile::is_ini(""));
+    }
+
+    #[test]
+    fn reads_files_with_a_byte_order_mark() {
+        let content = "\u{feff}{\"version\": 1, \"profiles\": {\"dev\": {\"target\": \"i-1\"}}}";
+
+        let file = SessionsFile::parse(content, "sessions").unwrap();
+
+        assert_eq!(file.profiles["dev"].target.as_deref(), Some("i-1"));
+    }
+
+    #[test]
+    fn locates_keys_only_where_they_are_members() {
+        let content = r#"{
+  "version": 1,
+  "profiles": {
+    "ops": {
+      "description": "copy of \"dev\", \"local_port\": 1",
+      "target": "dev"
+    },
+    "dev": {
+      "tags": ["local_port"],
+      "forwards": { "db": { "target": "i-2", "local_port": 70000 } },
+      "local_port": 0
+    }
+  }
+}"#;
+        let line = |keys: &[&str]| {
+            let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+            locate(content, &keys)
+        };
+
+        assert_eq!(line(&["profiles", "dev"]), 8);
+        assert_eq!(line(&["profiles", "dev", "local_port"]), 11);
+        assert_eq!(
+            line(&["profiles", "dev", "forwards", "db", "local_port"]),
+            10
+        );
+        assert_eq!(line(&["profiles", "dev", "host"]), 8);
+
+        let (_, invalid) = SessionsFile::parse_lenient(content, "sessions").unwrap();
+        assert_eq!(invalid[0].line, 10);
+    }
+
+    #[test]
+    fn keeps_comments_as_descriptions() {
+        let document = IniDocument::parse(LEGACY);
+        let file = SessionsFile::from_ini(&document, "sessions", &["dev".to_string()]).unwrap();
+
+        let dev = &file.profiles["dev"];
+        assert_eq!(dev.description.as_deref(), Some("Sessions of the team"));
+        assert_eq!(dev.local_port, Some(13389));
+
+        let db = &dev.forwards["db"];
+        assert_eq!(
+            db.description.as_deref(),
+            Some("Postgres\nDatabase through the bastion\nreachable from the VPN only")
+        );
+        assert_eq!(db.remote_port, Some(5432));
+    }
+}
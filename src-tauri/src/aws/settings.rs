@@ -0,0 +1,154 @@
+use crate::util::dirs::get_user_config_file;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use time::Duration;
+
+/// Which implementation performs the STS and IAM calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Cli,
+    Native,
+}
+
+/// AWS related preferences, read from the `aws` object of the client configuration file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AwsSettings {
+    pub session_log_lines: usize,
+    pub session_log_files: bool,
+    pub ready_timeout_secs: u64,
+    pub refresh_lead_secs: u64,
+    pub auto_port: bool,
+    pub port_range_start: u16,
+    pub port_range_end: u16,
+    pub terminal: Option<String>,
+    pub aws_cli_path: Option<String>,
+    pub session_manager_plugin_path: Option<String>,
+    pub profile_cache_ttl_secs: u64,
+    pub profile_lookup_concurrency: usize,
+    pub command_timeout_secs: u64,
+    pub command_timeouts: HashMap<String, u64>,
+    pub backend: BackendKind,
+    pub sts_endpoint: String,
+    pub iam_endpoint: String,
+    pub sso_portal_endpoint: Option<String>,
+    pub sso_login_timeout_secs: u64,
+    pub write_derived_credentials: bool,
+    pub derived_profile_suffix: String,
+}
+
+impl Default for AwsSettings {
+    fn default() -> Self {
+        Self {
+            session_log_lines: 1000,
+            session_log_files: false,
+            ready_timeout_secs: 30,
+            refresh_lead_secs: 300,
+            auto_port: false,
+            port_range_start: 13389,
+            port_range_end: 13489,
+            terminal: None,
+            aws_cli_path: None,
+            session_manager_plugin_path: None,
+            profile_cache_ttl_secs: 86400,
+            profile_lookup_concurrency: 4,
+            command_timeout_secs: 30,
+            command_timeouts: HashMap::new(),
+            backend: BackendKind::Cli,
+            sts_endpoint: "https://sts.amazonaws.com".to_string(),
+            iam_endpoint: "https://iam.amazonaws.com".to_string(),
+            sso_portal_endpoint: None,
+            sso_login_timeout_secs: 300,
+            write_derived_credentials: false,
+            derived_profile_suffix: "-mfa".to_string(),
+        }
+    }
+}
+
+impl AwsSettings {
+    pub fn load() -> Self {
+        let Ok(content) = fs::read_to_string(get_user_config_file()) else {
+            return Self::default();
+        };
+
+        match serde_json::from_str::<Value>(&content) {
+            Ok(mut config) => match config.get_mut("aws").map(Value::take) {
+                Some(Value::Object(aws)) => Self::from_fields(aws),
+                Some(Value::Null) | None => Self::default(),
+                Some(_) => {
+                    log::warn!("Ignoring the aws settings: expected an object");
+                    Self::default()
+                }
+            },
+            Err(e) => {
+                log::warn!("Ignoring the client configuration file: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Keep the default of every setting that does not deserialize, instead of dropping the
+    /// others along with it.
+    fn from_fields(aws: Map<String, Value>) -> Self {
+        let valid: Map<String, Value> = aws
+            .into_iter()
+            .filter(|(key, value)| {
+                let field = Map::from_iter([(key.clone(), value.clone())]);
+                match serde_json::from_value::<Self>(Value::Object(field)) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        log::warn!("Ignoring the aws setting '{}': {}", key, e);
+                        false
+                    }
+                }
+            })
+            .collect();
+
+        serde_json::from_value(Value::Object(valid)).unwrap_or_else(|e| {
+            log::warn!("Ignoring the aws settings: {}", e);
+            Self::default()
+        })
+    }
+
+    /// How long before expiration session credentials are considered stale.
+    pub fn refresh_lead(&self) -> Duration {
+        Duration::seconds(self.refresh_lead_secs as i64)
+    }
+
+    /// Deadline for one AWS call, keyed like the CLI invocation, e.g. `sts get-session-token`.
+    pub fn command_timeout(&self, operation: &str) -> std::time::Duration {
+        let secs = self
+            .command_timeouts
+            .get(operation)
+            .copied()
+            .unwrap_or(self.command_timeout_secs);
+        std::time::Duration::from_secs(secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn keeps_valid_settings_next_to_a_bad_one() {
+        let aws = json!({
+            "ready_timeout_secs": 60,
+            "refresh_lead_secs": "ten minutes",
+            "backend": "native",
+        });
+        let Value::Object(aws) = aws else {
+            unreachable!()
+        };
+
+        let settings = AwsSettings::from_fields(aws);
+
+        assert_eq!(settings.ready_timeout_secs, 60);
+        assert_eq!(settings.refresh_lead_secs, 300);
+        assert_eq!(settings.backend, BackendKind::Native);
+    }
+}
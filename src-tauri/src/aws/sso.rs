@@ -0,0 +1,134 @@
+use super::aws_config::AwsProfile;
+use super::aws_error::AwsErrorKind;
+use super::backend::AwsBackend;
+use super::cancellation;
+use super::models::SessionCredentials;
+use super::settings::AwsSettings;
+use super::tooling::Tooling;
+use super::utils::get_aws_sso_cache_dir;
+use crate::error::{AppError, Result};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::process::Stdio;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Access token written by `aws sso login` to `~/.aws/sso/cache`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SsoToken {
+    access_token: String,
+    expires_at: String,
+}
+
+impl SsoToken {
+    fn is_valid(&self) -> bool {
+        // Older CLI releases write `2024-01-01T00:00:00UTC`
+        let expires_at = self.expires_at.replace("UTC", "Z");
+
+        OffsetDateTime::parse(&expires_at, &Rfc3339)
+            .map(|expiration| expiration > OffsetDateTime::now_utc())
+            .unwrap_or(false)
+    }
+}
+
+/// Credentials for IAM Identity Center profiles, from the token cache the CLI maintains.
+pub struct SsoManager;
+
+impl SsoManager {
+    /// Exchange the cached access token for role credentials, signing in first when the
+    /// token is missing or expired.
+    pub async fn get_credentials(
+        backend: &dyn AwsBackend,
+        profile: &AwsProfile,
+    ) -> Result<SessionCredentials> {
+        let (Some(account_id), Some(role_name)) = (&profile.sso_account_id, &profile.sso_role_name)
+        else {
+            return Err(AppError::Custom(format!(
+                "Profile '{}' is not an SSO profile",
+                profile.name
+            )));
+        };
+        let region = profile
+            .sso_region
+            .as_deref()
+            .or(profile.region.as_deref())
+            .ok_or_else(|| {
+                AppError::Custom(format!("Profile '{}' has no sso_region", profile.name))
+            })?;
+
+        let token = match Self::cached_token(profile) {
+            Some(token) => token,
+            None => Self::login_for_token(profile).await?,
+        };
+
+        match backend
+            .get_role_credentials(account_id, role_name, &token.access_token, region)
+            .await
+        {
+            // The token was revoked before its expiration, so sign in again
+            Err(e) if Self::is_unauthorized(&e) => {
+                let token = Self::login_for_token(profile).await?;
+                backend
+                    .get_role_credentials(account_id, role_name, &token.access_token, region)
+                    .await
+            }
+            result => result,
+        }
+    }
+
+    async fn login_for_token(profile: &AwsProfile) -> Result<SsoToken> {
+        Self::login(&profile.name).await?;
+
+        Self::cached_token(profile).ok_or_else(|| {
+            AppError::Custom(format!("SSO login for '{}' did not complete", profile.name))
+        })
+    }
+
+    fn is_unauthorized(error: &AppError) -> bool {
+        matches!(error.aws_kind(), Some(AwsErrorKind::Other(code)) if code == "UnauthorizedException")
+    }
+
+    /// Run `aws sso login`, which opens the browser and waits for the user to approve.
+    pub async fn login(profile: &str) -> Result<()> {
+        let settings = AwsSettings::load();
+
+        let mut command = Tooling::aws_command(&settings);
+        command
+            .args(["sso", "login", "--profile", profile])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let child = command.spawn()?;
+        let output = cancellation::guard(
+            "sso login",
+            Duration::from_secs(settings.sso_login_timeout_secs),
+            async { Ok(child.wait_with_output().await?) },
+        )
+        .await?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            let err = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            Err(AppError::AwsCli(err))
+        }
+    }
+
+    /// The valid token of a profile, if any. Tokens are stored under the SHA-1 of the
+    /// `sso_session` name, or of the start URL for legacy profiles.
+    fn cached_token(profile: &AwsProfile) -> Option<SsoToken> {
+        let key = profile
+            .sso_session
+            .as_deref()
+            .or(profile.sso_start_url.as_deref())?;
+        let file_name = format!("{}.json", hex::encode(Sha1::digest(key.as_bytes())));
+        let path = get_aws_sso_cache_dir().ok()?.join(file_name);
+
+        let token: SsoToken = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+        token.is_valid().then_some(token)
+    }
+}
@@ -0,0 +1,162 @@
+use super::backend::SharedBackend;
+use super::credentials::CredentialManager;
+use super::session::SessionManager;
+use super::session_log::LogStream;
+use super::session_registry::{ChildState, SessionRegistry};
+use crate::error::AppError;
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+const MAX_ATTEMPTS: u32 = 8;
+
+pub const EVENT_RECONNECTING: &str = "session-reconnecting";
+pub const EVENT_RECONNECTED: &str = "session-reconnected";
+pub const EVENT_GAVE_UP: &str = "session-gave-up";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconnectEvent {
+    pub id: u64,
+    pub profile: String,
+    pub attempt: u32,
+    pub delay_secs: u64,
+    pub error: Option<String>,
+}
+
+/// Watches a port-forwarding session and restarts it with exponential backoff when it exits.
+pub struct SessionSupervisor;
+
+impl SessionSupervisor {
+    pub fn spawn(app: AppHandle, id: u64) {
+        tauri::async_runtime::spawn(async move {
+            Self::supervise(app, id).await;
+        });
+    }
+
+    async fn supervise(app: AppHandle, id: u64) {
+        let registry = app.state::<SessionRegistry>();
+
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            match registry.poll(id).await {
+                None => return,
+                Some(ChildState::Running) => continue,
+                Some(ChildState::Exited(status)) => {
+                    log::warn!("Session {} exited ({:?}), reconnecting", id, status);
+                    Self::forget_expired_credentials(&registry, id).await;
+                }
+            }
+
+            if !Self::reconnect(&app, &registry, id).await {
+                let _ = registry.stop(id).await;
+                return;
+            }
+        }
+    }
+
+    /// Avoid reconnecting with cached credentials the session was just rejected with. Only
+    /// the output of the child that exited counts, earlier runs may have failed for reasons
+    /// long fixed.
+    async fn forget_expired_credentials(registry: &SessionRegistry, id: u64) {
+        if let (Some(session), Ok(log)) = (registry.get(id).await, registry.log(id).await) {
+            let stderr = log.text(Some(LogStream::Stderr));
+            CredentialManager::handle_error(&session.profile, &AppError::AwsCli(stderr));
+        }
+    }
+
+    /// Start the session again. Returns whether it is running, either restarted here or by
+    /// someone else meanwhile.
+    async fn reconnect(app: &AppHandle, registry: &SessionRegistry, id: u64) -> bool {
+        let mut delay = INITIAL_BACKOFF;
+        let mut last_error = None;
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            let Some(session) = registry.get(id).await else {
+                return false;
+            };
+
+            let _ = app.emit(
+                EVENT_RECONNECTING,
+                ReconnectEvent {
+                    id,
+                    profile: session.profile.clone(),
+                    attempt,
+                    delay_secs: delay.as_secs(),
+                    error: last_error.clone(),
+                },
+            );
+
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_BACKOFF);
+
+            // The credential refresher may have restarted the session during the backoff
+            match registry.poll(id).await {
+                None => return false,
+                Some(ChildState::Running) => return true,
+                Some(ChildState::Exited(_)) => {}
+            }
+
+            let result = async {
+                let backend = app.state::<SharedBackend>();
+                let (credentials, _) =
+                    CredentialManager::resolve(backend.as_ref(), &session.profile).await?;
+                let child =
+                    SessionManager::start_session(&session.profile, &session.config, &credentials)
+                        .await?;
+                Ok::<_, AppError>((child, credentials.expiration))
+            }
+            .await;
+
+            match result {
+                Ok((child, expiration)) => {
+                    if let Err(mut orphan) =
+                        registry.replace_child(app, id, child, expiration).await
+                    {
+                        let _ = SessionRegistry::terminate(&mut orphan).await;
+                        return false;
+                    }
+
+                    let _ = app.emit(
+                        EVENT_RECONNECTED,
+                        ReconnectEvent {
+                            id,
+                            profile: session.profile,
+                            attempt,
+                            delay_secs: 0,
+                            error: None,
+                        },
+                    );
+                    return true;
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Reconnect attempt {} for session {} failed: {}",
+                        attempt,
+                        id,
+                        e
+                    );
+                    last_error = Some(e.to_string());
+                }
+            }
+        }
+
+        if let Some(session) = registry.get(id).await {
+            let _ = app.emit(
+                EVENT_GAVE_UP,
+                ReconnectEvent {
+                    id,
+                    profile: session.profile,
+                    attempt: MAX_ATTEMPTS,
+                    delay_secs: 0,
+                    error: last_error,
+                },
+            );
+        }
+
+        false
+    }
+}
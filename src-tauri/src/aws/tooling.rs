@@ -0,0 +1,385 @@
+use super::settings::AwsSettings;
+use super::utils::apply_aws_files;
+use crate::error::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio::process::Command;
+
+/// The first CLI release with `ssm start-session`. `aws sso login` additionally needs v2,
+/// which the CLI reports itself.
+const AWS_CLI_MIN_VERSION: &str = "1.16.12";
+/// Port forwarding to remote hosts needs at least this plugin release.
+const PLUGIN_MIN_VERSION: &str = "1.2.285.0";
+
+/// How long a `--version` probe may take before the binary is reported as unusable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Versions by binary and its modification time. The tools are probed before every session,
+/// and starting the CLI costs about a second.
+static VERSIONS: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, String)>>> = OnceLock::new();
+
+const AWS_CLI_INSTALL_URL: &str =
+    "https://docs.aws.amazon.com/cli/latest/userguide/getting-started-install.html";
+const PLUGIN_INSTALL_URL: &str = "https://docs.aws.amazon.com/systems-manager/latest/userguide/session-manager-working-with-install-plugin.html";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tool {
+    AwsCli,
+    SessionManagerPlugin,
+}
+
+impl Tool {
+    fn binary(self) -> &'static str {
+        match self {
+            Tool::AwsCli => "aws",
+            Tool::SessionManagerPlugin => "session-manager-plugin",
+        }
+    }
+
+    fn setting(self) -> &'static str {
+        match self {
+            Tool::AwsCli => "aws_cli_path",
+            Tool::SessionManagerPlugin => "session_manager_plugin_path",
+        }
+    }
+
+    fn minimum_version(self) -> &'static str {
+        match self {
+            Tool::AwsCli => AWS_CLI_MIN_VERSION,
+            Tool::SessionManagerPlugin => PLUGIN_MIN_VERSION,
+        }
+    }
+
+    fn install_url(self) -> &'static str {
+        match self {
+            Tool::AwsCli => AWS_CLI_INSTALL_URL,
+            Tool::SessionManagerPlugin => PLUGIN_INSTALL_URL,
+        }
+    }
+
+    fn configured_path(self, settings: &AwsSettings) -> Option<&str> {
+        match self {
+            Tool::AwsCli => settings.aws_cli_path.as_deref(),
+            Tool::SessionManagerPlugin => settings.session_manager_plugin_path.as_deref(),
+        }
+        .filter(|path| !path.trim().is_empty())
+    }
+
+    /// Install locations checked when the binary is not on the `PATH`.
+    fn known_locations(self) -> &'static [&'static str] {
+        match self {
+            Tool::AwsCli if cfg!(target_os = "windows") => {
+                &[r"C:\Program Files\Amazon\AWSCLIV2\aws.exe"]
+            }
+            Tool::AwsCli => &["/usr/local/bin/aws", "/opt/homebrew/bin/aws"],
+            Tool::SessionManagerPlugin if cfg!(target_os = "windows") => {
+                &[r"C:\Program Files\Amazon\SessionManagerPlugin\bin\session-manager-plugin.exe"]
+            }
+            Tool::SessionManagerPlugin => &[
+                "/usr/local/bin/session-manager-plugin",
+                "/usr/local/sessionmanagerplugin/bin/session-manager-plugin",
+                "/opt/homebrew/bin/session-manager-plugin",
+            ],
+        }
+    }
+
+    /// Extract the version number from the `--version` output.
+    fn parse_version(self, output: &str) -> Option<String> {
+        let text = match self {
+            // aws-cli/2.15.30 Python/3.11.8 Windows/10 exe/AMD64 prompt/off
+            Tool::AwsCli => output.split_whitespace().next()?.strip_prefix("aws-cli/")?,
+            // 1.2.553.0
+            Tool::SessionManagerPlugin => output.lines().next()?.trim(),
+        };
+
+        parse_numbers(text).map(|_| text.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolState {
+    Ok,
+    Missing,
+    Outdated,
+    Unrecognized,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolStatus {
+    pub tool: Tool,
+    pub path: Option<String>,
+    pub version: Option<String>,
+    pub minimum_version: String,
+    pub state: ToolState,
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolingReport {
+    pub aws_cli: ToolStatus,
+    pub session_manager_plugin: ToolStatus,
+    pub ready: bool,
+}
+
+impl ToolingReport {
+    fn problems(&self) -> Vec<&str> {
+        [&self.aws_cli, &self.session_manager_plugin]
+            .into_iter()
+            .filter_map(|status| status.hint.as_deref())
+            .collect()
+    }
+}
+
+/// Locates the external binaries sessions depend on and checks their versions.
+pub struct Tooling;
+
+impl Tooling {
+    pub async fn probe(settings: &AwsSettings) -> ToolingReport {
+        let aws_cli = Self::probe_tool(Tool::AwsCli, settings).await;
+        let session_manager_plugin = Self::probe_tool(Tool::SessionManagerPlugin, settings).await;
+        let ready = aws_cli.state == ToolState::Ok && session_manager_plugin.state == ToolState::Ok;
+
+        ToolingReport {
+            aws_cli,
+            session_manager_plugin,
+            ready,
+        }
+    }
+
+    /// Fail with the fix-it hints unless both binaries are usable.
+    pub async fn ensure_ready(settings: &AwsSettings) -> Result<()> {
+        let report = Self::probe(settings).await;
+
+        if report.ready {
+            Ok(())
+        } else {
+            Err(AppError::Custom(report.problems().join("\n")))
+        }
+    }
+
+    /// The AWS CLI executable, falling back to the bare name for the OS to look up.
+    pub fn aws_cli(settings: &AwsSettings) -> PathBuf {
+        Self::resolve(Tool::AwsCli, settings)
+            .unwrap_or_else(|| PathBuf::from(Tool::AwsCli.binary()))
+    }
+
+    /// Build a command running the AWS CLI, able to find a configured session-manager-plugin
+    /// and reading the same config and credentials files as akaw.
+    pub fn aws_command(settings: &AwsSettings) -> Command {
+        let mut command = Command::new(Self::aws_cli(settings));
+        Self::apply_plugin_path(&mut command, settings);
+        apply_aws_files(&mut command);
+        command
+    }
+
+    /// The CLI looks the plugin up on the `PATH`, so put the directory of the resolved
+    /// plugin first, whether it is configured or was found in a known install location.
+    pub fn apply_plugin_path(command: &mut Command, settings: &AwsSettings) {
+        let Some(plugin) = Self::resolve(Tool::SessionManagerPlugin, settings) else {
+            return;
+        };
+
+        if let Some(plugin_dir) = plugin.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            let mut paths = vec![plugin_dir.to_path_buf()];
+            if let Some(path) = std::env::var_os("PATH") {
+                paths.extend(std::env::split_paths(&path).filter(|dir| dir != plugin_dir));
+            }
+            if let Ok(path) = std::env::join_paths(paths) {
+                command.env("PATH", path);
+            }
+        }
+    }
+
+    /// The configured path when set, otherwise the first match on the `PATH` or in a known
+    /// install location.
+    fn resolve(tool: Tool, settings: &AwsSettings) -> Option<PathBuf> {
+        if let Some(path) = tool.configured_path(settings) {
+            return Some(PathBuf::from(path));
+        }
+
+        let name = if cfg!(target_os = "windows") {
+            format!("{}.exe", tool.binary())
+        } else {
+            tool.binary().to_string()
+        };
+
+        std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|dir| dir.join(&name))
+            .chain(tool.known_locations().iter().map(PathBuf::from))
+            .find(|candidate| candidate.is_file())
+    }
+
+    async fn probe_tool(tool: Tool, settings: &AwsSettings) -> ToolStatus {
+        let mut status = ToolStatus {
+            tool,
+            path: None,
+            version: None,
+            minimum_version: tool.minimum_version().to_string(),
+            state: ToolState::Missing,
+            hint: None,
+        };
+
+        let path = match Self::resolve(tool, settings) {
+            Some(path) if path.is_file() => path,
+            Some(path) => {
+                status.hint = Some(format!(
+                    "'{}' is set to {}, which does not exist. Fix the path or remove the setting",
+                    tool.setting(),
+                    path.display()
+                ));
+                return status;
+            }
+            None => {
+                status.hint = Some(format!(
+                    "{} was not found. Install it from {} or set '{}' in the aws settings",
+                    tool.binary(),
+                    tool.install_url(),
+                    tool.setting()
+                ));
+                return status;
+            }
+        };
+        status.path = Some(path.display().to_string());
+
+        let version = match Self::version(tool, &path).await {
+            Ok(version) => version,
+            Err(reason) => {
+                status.state = ToolState::Unrecognized;
+                status.hint = Some(format!(
+                    "Could not determine the version of {}: {}",
+                    path.display(),
+                    reason
+                ));
+                return status;
+            }
+        };
+
+        if is_older(&version, tool.minimum_version()) {
+            status.state = ToolState::Outdated;
+            status.hint = Some(format!(
+                "{} {} is older than the required {}. Update it from {}",
+                tool.binary(),
+                version,
+                tool.minimum_version(),
+                tool.install_url()
+            ));
+        } else {
+            status.state = ToolState::Ok;
+        }
+
+        status.version = Some(version);
+        status
+    }
+
+    /// The version printed by `--version`, remembered until the binary is modified.
+    async fn version(tool: Tool, path: &Path) -> std::result::Result<String, String> {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        let versions = VERSIONS.get_or_init(Default::default);
+
+        if let (Some(modified), Ok(versions)) = (modified, versions.lock()) {
+            if let Some((_, version)) = versions.get(path).filter(|(at, _)| *at == modified) {
+                return Ok(version.clone());
+            }
+        }
+
+        let output = tokio::time::timeout(
+            PROBE_TIMEOUT,
+            Command::new(path)
+                .arg("--version")
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await
+        .map_err(|_| {
+            format!(
+                "--version did not finish within {} seconds",
+                PROBE_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| e.to_string())?;
+
+        // Older CLI releases print the version on stderr
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let version = tool
+            .parse_version(&stdout)
+            .or_else(|| tool.parse_version(&stderr))
+            .ok_or_else(|| stderr.trim().to_string())?;
+
+        if let (Some(modified), Ok(mut versions)) = (modified, versions.lock()) {
+            versions.insert(path.to_path_buf(), (modified, version.clone()));
+        }
+
+        Ok(version)
+    }
+}
+
+/// The leading number of every dotted part, so that `2.15.30dev0` reads as 2.15.30.
+fn parse_numbers(version: &str) -> Option<Vec<u64>> {
+    version
+        .split('.')
+        .map(|part| {
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            part[..digits].parse().ok()
+        })
+        .collect()
+}
+
+/// Compare dotted versions part by part, treating missing parts as zero.
+fn is_older(version: &str, minimum: &str) -> bool {
+    let (Some(version), Some(minimum)) = (parse_numbers(version), parse_numbers(minimum)) else {
+        return false;
+    };
+
+    let len = version.len().max(minimum.len());
+    let part = |parts: &[u64], i: usize| parts.get(i).copied().unwrap_or(0);
+
+    (0..len)
+        .map(|i| part(&version, i).cmp(&part(&minimum, i)))
+        .find(|ordering| ordering.is_ne())
+        .is_some_and(|ordering| ordering.is_lt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_versions_with_a_suffix() {
+        let output = "aws-cli/2.15.30dev0 Python/3.11.8 Linux/6.5.0 source/x86_64";
+
+        assert_eq!(
+            Tool::AwsCli.parse_version(output).as_deref(),
+            Some("2.15.30dev0")
+        );
+        assert!(!is_older("2.15.30dev0", "2.15.30"));
+        assert!(is_older("2.15.29rc1", "2.15.30"));
+        assert_eq!(
+            Tool::AwsCli.parse_version("aws-cli/dev Python/3.11.8"),
+            None
+        );
+    }
+
+    #[test]
+    fn accepts_cli_v1_with_session_manager_support() {
+        let output = "aws-cli/1.18.69 Python/3.8.10 Linux/5.4.0 botocore/1.17.0";
+        let version = Tool::AwsCli.parse_version(output).unwrap();
+
+        assert!(!is_older(&version, Tool::AwsCli.minimum_version()));
+        assert!(is_older("1.16.11", Tool::AwsCli.minimum_version()));
+    }
+}
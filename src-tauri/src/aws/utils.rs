@@ -1,29 +1,74 @@
 use crate::error::{AppError, Result};
+use crate::util::dirs::get_home_root;
 use dirs::home_dir;
+use std::env;
 use std::path::PathBuf;
+use tokio::process::Command;
 
-pub fn check_aws_cli() -> bool {
-    std::process::Command::new("aws")
-        .arg("--version")
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
-}
+/// Overrides of the shared files honored by the AWS CLI and SDKs.
+pub const AWS_CONFIG_FILE_ENV: &str = "AWS_CONFIG_FILE";
+pub const AWS_SHARED_CREDENTIALS_FILE_ENV: &str = "AWS_SHARED_CREDENTIALS_FILE";
+/// Override of the session configuration file.
+pub const AKAW_SESSIONS_FILE_ENV: &str = "AKAW_SESSIONS_FILE";
 
 pub fn get_aws_config_path() -> Result<PathBuf> {
-    let home =
-        home_dir().ok_or_else(|| AppError::Custom("Home directory not found".to_string()))?;
-    Ok(PathBuf::from(home).join(".aws").join("config"))
+    resolve_path(AWS_CONFIG_FILE_ENV, "config")
 }
 
 pub fn get_aws_credentials_path() -> Result<PathBuf> {
+    resolve_path(AWS_SHARED_CREDENTIALS_FILE_ENV, "credentials")
+}
+
+pub fn get_aws_sessions_path() -> Result<PathBuf> {
+    resolve_path(AKAW_SESSIONS_FILE_ENV, "sessions.json")
+}
+
+/// The ini sessions file used before the versioned format, read until it is migrated.
+pub fn get_legacy_sessions_path() -> Result<PathBuf> {
+    Ok(get_aws_dir()?.join("sessions"))
+}
+
+pub fn get_aws_sso_cache_dir() -> Result<PathBuf> {
+    // The CLI has no override for the token cache and always writes it to the real home folder
     let home =
         home_dir().ok_or_else(|| AppError::Custom("Home directory not found".to_string()))?;
-    Ok(PathBuf::from(home).join(".aws").join("credentials"))
+    Ok(home.join(".aws").join("sso").join("cache"))
 }
 
-pub fn get_aws_sessions_path() -> Result<PathBuf> {
+/// Point a child AWS CLI at the files akaw resolved, which differ from the CLI's own defaults
+/// in portable mode.
+pub fn apply_aws_files(command: &mut Command) {
+    if let Ok(path) = get_aws_config_path() {
+        command.env(AWS_CONFIG_FILE_ENV, path);
+    }
+    if let Ok(path) = get_aws_credentials_path() {
+        command.env(AWS_SHARED_CREDENTIALS_FILE_ENV, path);
+    }
+}
+
+/// The file named by `var` when set, otherwise `file_name` in the `.aws` folder of the home
+/// folder or portable root. Like the CLI, a leading `~` in the variable is expanded.
+fn resolve_path(var: &str, file_name: &str) -> Result<PathBuf> {
+    if let Some(value) = env::var(var).ok().filter(|v| !v.trim().is_empty()) {
+        return expand_user(value.trim());
+    }
+
+    Ok(get_aws_dir()?.join(file_name))
+}
+
+fn get_aws_dir() -> Result<PathBuf> {
+    let root =
+        get_home_root().ok_or_else(|| AppError::Custom("Home directory not found".to_string()))?;
+    Ok(root.join(".aws"))
+}
+
+fn expand_user(path: &str) -> Result<PathBuf> {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => rest,
+        _ => return Ok(PathBuf::from(path)),
+    };
+
     let home =
         home_dir().ok_or_else(|| AppError::Custom("Home directory not found".to_string()))?;
-    Ok(PathBuf::from(home).join(".aws").join("sessions"))
+    Ok(home.join(rest.trim_start_matches(['/', '\\'])))
 }
@@ -5,6 +5,12 @@ pub enum AppError {
     #[error("AWS CLI error: {0}")]
     AwsCli(String),
 
+    #[error("AWS error: {0}")]
+    Aws(String),
+
+    #[error("HTTP error: {0}")]
+    Http(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -23,9 +29,21 @@ pub enum AppError {
     #[error("Port already in use: {0}")]
     PortInUse(u16),
 
+    #[error("Session not found: {0}")]
+    SessionNotFound(u64),
+
+    #[error("{0} timed out after {1}s")]
+    Timeout(String, u64),
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
     #[error("Keyring error: {0}")]
     Keyring(#[from] keyring::Error),
 
+    #[error("{0}:{1}: {2}")]
+    InvalidConfig(String, usize, String),
+
     #[error("{0}")]
     Custom(String),
 }
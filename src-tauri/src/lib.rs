@@ -6,7 +6,14 @@ mod commands;
 mod error;
 mod util;
 
-use tauri::{App, Manager};
+use aws::backend::create_backend;
+use aws::cancellation::OperationRegistry;
+use aws::credential_process::{self, CredentialProcess};
+use aws::derived_profile::DerivedProfile;
+use aws::pid_file::PidFile;
+use aws::refresh::CredentialRefresher;
+use aws::session_registry::SessionRegistry;
+use tauri::{App, AppHandle, Manager, RunEvent};
 use tauri_plugin_log::{log::LevelFilter, Target, TargetKind};
 use time::OffsetDateTime;
 use util::dirs::get_user_logs_dir;
@@ -14,8 +21,15 @@ use util::dirs::get_user_logs_dir;
 use crate::util::formatter::log_time_fmt;
 
 fn initialize(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
+    PidFile::reap_stale();
+    if let Err(e) = DerivedProfile::prune_expired() {
+        log::warn!("Failed to prune derived credentials: {}", e);
+    }
+
     let _ = app::tray::create_tray(app);
 
+    CredentialRefresher::spawn(app.handle().clone());
+
     let window = app.get_webview_window("main").unwrap();
 
     app::window::set_window_position(&window);
@@ -24,6 +38,44 @@ fn initialize(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn shutdown(app: &AppHandle) {
+    let registry = app.state::<SessionRegistry>();
+    let stopped = tauri::async_runtime::block_on(registry.stop_all());
+
+    if !stopped.is_empty() {
+        log::info!("Terminated {} session(s) on exit", stopped.len());
+    }
+}
+
+/// Whether the app was started by another tool through `credential_process`.
+pub fn is_credential_process(args: &[String]) -> bool {
+    args.first().map(String::as_str) == Some(credential_process::ENTRY_ARG)
+}
+
+/// Print the session credentials of `--profile` and return the exit code, without starting the UI.
+pub fn credential_process(args: &[String]) -> i32 {
+    let profile = args
+        .iter()
+        .position(|arg| arg == "--profile")
+        .and_then(|i| args.get(i + 1));
+
+    let Some(profile) = profile else {
+        eprintln!("Usage: akaw {} --profile <name>", credential_process::ENTRY_ARG);
+        return 2;
+    };
+
+    match tauri::async_runtime::block_on(CredentialProcess::run(profile)) {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
 pub fn run() {
     #[cfg(not(rust_analyzer))]
     let context = tauri::generate_context!();
@@ -58,23 +110,44 @@ pub fn run() {
                 ])
                 .build(),
         )
+        .manage(create_backend())
+        .manage(SessionRegistry::default())
+        .manage(OperationRegistry::default())
         .setup(initialize)
         .invoke_handler(tauri::generate_handler![
             commands::config::load_config,
             commands::config::save_config,
             commands::command::execute_command,
             aws::commands::list_aws_profiles,
+            aws::commands::refresh_aws_profiles,
             aws::commands::show_aws_config,
             aws::commands::setup_mfa_device,
+            aws::commands::cancel_operation,
             aws::commands::connect,
+            aws::commands::open_shell,
+            aws::commands::install_credential_process,
+            aws::commands::activate_derived_profile,
+            aws::commands::check_tooling,
+            aws::commands::validate_configuration,
             aws::commands::remove_aws_profile,
             aws::commands::generate_totp_code,
             aws::commands::remove_mfa_device,
             aws::commands::get_profile_names,
+            aws::commands::get_profile_details,
             aws::commands::check_mfa_status,
             aws::commands::init_aws_configs,
+            aws::commands::migrate_sessions,
             aws::commands::show_aws_config,
+            aws::commands::list_sessions,
+            aws::commands::stop_session,
+            aws::commands::stop_all_sessions,
+            aws::commands::get_session_log,
         ])
-        .run(context)
-        .expect("error while running tauri application");
+        .build(context)
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                shutdown(app);
+            }
+        });
 }
@@ -2,5 +2,11 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if akaw::is_credential_process(&args) {
+        std::process::exit(akaw::credential_process(&args));
+    }
+
     akaw::run();
 }
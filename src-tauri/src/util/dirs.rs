@@ -1,19 +1,64 @@
 use dirs::home_dir;
+use std::env;
 use std::path::PathBuf;
 
+/// Root directory used in place of the user's home folder, for running from a portable drive.
+pub const PORTABLE_ROOT_ENV: &str = "AKAW_PORTABLE_ROOT";
+
+/// File next to the executable that turns on portable mode with the executable's folder as root.
+const PORTABLE_MARKER_FILE: &str = "akaw.portable";
+
 /// The specific path to the main client configuration file.
 pub fn get_user_config_file() -> PathBuf {
     get_user_home_dir().join("config.json")
 }
 
+/// The file remembering the last local port used by each profile.
+pub fn get_user_ports_file() -> PathBuf {
+    get_user_home_dir().join("ports.json")
+}
+
+/// The file caching the identity and MFA serial looked up for each profile.
+pub fn get_user_profile_cache_file() -> PathBuf {
+    get_user_home_dir().join("profiles.json")
+}
+
+/// The file listing the session processes spawned by the running app.
+pub fn get_user_pid_file() -> PathBuf {
+    get_user_home_dir().join("sessions.pid")
+}
+
 /// The directory for User-context logs.
 pub fn get_user_logs_dir() -> PathBuf {
     get_user_home_dir().join("logs")
 }
 
-/// The path to the global .wvs directory in the user's home folder.
+/// The directory for captured session-manager-plugin output.
+pub fn get_user_session_logs_dir() -> PathBuf {
+    get_user_logs_dir().join("sessions")
+}
+
+/// The path to the global .akaw directory in the user's home folder or portable root.
 pub fn get_user_home_dir() -> PathBuf {
-    let mut path = home_dir().expect("User home directory should be resolvable");
+    let mut path = get_home_root().expect("User home directory should be resolvable");
     path.push(".akaw");
     path
 }
+
+/// The portable root when running in portable mode, otherwise the user's home folder.
+pub fn get_home_root() -> Option<PathBuf> {
+    get_portable_root().or_else(home_dir)
+}
+
+/// The portable root, from `AKAW_PORTABLE_ROOT` or a marker file next to the executable.
+pub fn get_portable_root() -> Option<PathBuf> {
+    if let Some(root) = env::var_os(PORTABLE_ROOT_ENV).filter(|root| !root.is_empty()) {
+        return Some(PathBuf::from(root));
+    }
+
+    let exe_dir = env::current_exe().ok()?.parent()?.to_path_buf();
+    exe_dir
+        .join(PORTABLE_MARKER_FILE)
+        .is_file()
+        .then_some(exe_dir)
+}
@@ -1,2 +1,3 @@
 pub mod dirs;
 pub mod formatter;
+pub mod process;
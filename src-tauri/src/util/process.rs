@@ -0,0 +1,69 @@
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+/// Terminate a process together with everything it spawned.
+/// On Unix the process is expected to lead its own process group.
+pub fn kill_tree(pid: u32) {
+    #[cfg(target_os = "windows")]
+    let _ = Command::new("taskkill")
+        .args(["/PID", &pid.to_string(), "/T", "/F"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output();
+
+    #[cfg(unix)]
+    let _ = Command::new("kill")
+        .args(["-TERM", "--", &format!("-{}", pid)])
+        .output();
+}
+
+/// Whether a process, or on Unix anything left in the process group it leads, is still running.
+pub fn is_tree_running(pid: u32) -> bool {
+    #[cfg(target_os = "windows")]
+    return process_name(pid).is_some();
+
+    #[cfg(unix)]
+    return Command::new("kill")
+        .args(["-0", "--", &format!("-{}", pid)])
+        .output()
+        .is_ok_and(|output| output.status.success());
+}
+
+/// The file name of the executable of a running process, if it still exists.
+pub fn process_name(pid: u32) -> Option<String> {
+    #[cfg(target_os = "windows")]
+    let output = Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+
+    #[cfg(unix)]
+    let output = Command::new("ps")
+        .args(["-p", &pid.to_string(), "-o", "comm="])
+        .output()
+        .ok()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let name = stdout
+        .lines()
+        .next()?
+        .split(',')
+        .next()?
+        .trim()
+        .trim_matches('"');
+
+    if name.is_empty() || name.starts_with("INFO:") {
+        return None;
+    }
+
+    // macOS reports the full path of the executable
+    Path::new(name)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+}
@@ -7,16 +7,29 @@ type COMMAND =
 	| "fetch_commands"
 	// aws
 	| "list_aws_profiles"
+	| "refresh_aws_profiles"
 	| "show_aws_config"
 	| "setup_mfa_device"
+	| "cancel_operation"
 	| "connect"
+	| "open_shell"
+	| "install_credential_process"
+	| "activate_derived_profile"
+	| "check_tooling"
+	| "validate_configuration"
 	| "remove_aws_profile"
 	| "generate_totp_code"
 	| "remove_mfa_device"
 	| "get_profile_names"
+	| "get_profile_details"
 	| "check_mfa_status"
 	| "init_aws_configs"
+	| "migrate_sessions"
 	| "show_aws_config"
+	| "list_sessions"
+	| "stop_session"
+	| "stop_all_sessions"
+	| "get_session_log"
 	// config
 	| "load_config"
 	| "save_config";
@@ -24,8 +24,33 @@ export interface AppearanceSettings {
 	locale: string;
 }
 
+export interface AwsSettings {
+	session_log_lines?: number;
+	session_log_files?: boolean;
+	ready_timeout_secs?: number;
+	refresh_lead_secs?: number;
+	auto_port?: boolean;
+	port_range_start?: number;
+	port_range_end?: number;
+	terminal?: string;
+	aws_cli_path?: string;
+	session_manager_plugin_path?: string;
+	profile_cache_ttl_secs?: number;
+	profile_lookup_concurrency?: number;
+	command_timeout_secs?: number;
+	command_timeouts?: Record<string, number>;
+	backend?: "cli" | "native";
+	sts_endpoint?: string;
+	iam_endpoint?: string;
+	sso_portal_endpoint?: string;
+	sso_login_timeout_secs?: number;
+	write_derived_credentials?: boolean;
+	derived_profile_suffix?: string;
+}
+
 export interface AppConfig {
 	appearance: AppearanceSettings;
 	apps: App[];
 	commands: CommandList;
+	aws?: AwsSettings;
 }